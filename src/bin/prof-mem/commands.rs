@@ -0,0 +1,346 @@
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use prof_mem::{Frame, HeapProfile, ProfileMerger, write_profile_flamegraph};
+
+use crate::{Args, SortKey, usage_error};
+
+fn load(path: &str) -> io::Result<HeapProfile> {
+    HeapProfile::open(path).map_err(|err| io::Error::new(err.kind(), format!("{path}: {err}")))
+}
+
+/// the one file of the commands taking one.
+fn single_file(args: &Args) -> io::Result<&str> {
+    match args.files.as_slice() {
+        [file] => Ok(file),
+        _ => Err(usage_error(format!("{} takes one file", args.command))),
+    }
+}
+
+/// the index of the reported values, the `space` of the dumps of this crate or the
+/// `inuse_space` of the other heap profilers.
+fn value_index(profile: &HeapProfile, args: &Args) -> io::Result<usize> {
+    match &args.sample_index {
+        Some(kind) => profile
+            .sample_index(kind)
+            .or_else(|| kind.parse().ok())
+            .filter(|idx| *idx < profile.sample_types.len())
+            .ok_or_else(|| usage_error(format!("no sample type {kind} in the profile"))),
+        None => Ok(profile
+            .sample_index("space")
+            .or_else(|| profile.sample_index("inuse_space"))
+            .unwrap_or_default()),
+    }
+}
+
+/// the stack of a sample without the addresses, they change from a process to another.
+fn stack_key(frames: &[Frame]) -> Vec<Frame> {
+    frames
+        .iter()
+        .map(|frame| Frame {
+            address: 0,
+            ..frame.clone()
+        })
+        .collect()
+}
+
+fn site_name(frames: &[Frame]) -> String {
+    match frames.first() {
+        Some(frame) if frame.file.is_empty() && frame.function.is_empty() => {
+            format!("[unknown {:#x}]", frame.address)
+        }
+        Some(frame) if frame.file.is_empty() => frame.short_function(),
+        Some(frame) => format!("{} ({}:{})", frame.short_function(), frame.file, frame.line),
+        None => "[unknown]".to_string(),
+    }
+}
+
+fn percent(value: i64, total: i64) -> f64 {
+    match total {
+        0 => 0.0,
+        total => value as f64 * 100.0 / total as f64,
+    }
+}
+
+/// The samples of a stack added up.
+#[derive(Default)]
+struct Site {
+    frames: Vec<Frame>,
+    value: i64,
+    /// the blocks of the stack, scaled back by the sampling period.
+    count: i64,
+}
+
+impl Site {
+    fn avg_size(&self) -> i64 {
+        self.value.checked_div(self.count).unwrap_or_default()
+    }
+}
+
+fn sites(profile: &HeapProfile, index: usize) -> HashMap<Vec<Frame>, Site> {
+    let blocks = profile.period.max(1);
+    let mut sites: HashMap<Vec<Frame>, Site> = HashMap::new();
+    for sample in &profile.samples {
+        let site = sites.entry(stack_key(&sample.frames)).or_default();
+        if site.frames.is_empty() {
+            site.frames = sample.frames.clone();
+        }
+        site.value += sample.values.get(index).copied().unwrap_or_default();
+        site.count += blocks;
+    }
+    sites
+}
+
+pub(crate) fn top(args: &Args) -> io::Result<()> {
+    let profile = load(single_file(args)?)?;
+    let index = value_index(&profile, args)?;
+    let total = profile.total(index);
+    let mut sites: Vec<_> = sites(&profile, index).into_values().collect();
+    sites.sort_by_key(|site| {
+        Reverse(match args.sort {
+            SortKey::Inuse => site.value,
+            SortKey::Count => site.count,
+            SortKey::AvgSize => site.avg_size(),
+        })
+    });
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:>12} {:>7} {:>9} {:>10}  site",
+        "inuse bytes", "inuse%", "count", "avg size"
+    )?;
+    for site in sites.iter().take(args.n) {
+        writeln!(
+            out,
+            "{:>12} {:>6.2}% {:>9} {:>10}  {}",
+            site.value,
+            percent(site.value, total),
+            site.count,
+            site.avg_size(),
+            site_name(&site.frames)
+        )?;
+    }
+    writeln!(out, "total inuse bytes: {total}")
+}
+
+#[derive(Default)]
+struct TreeNode {
+    value: i64,
+    children: BTreeMap<String, TreeNode>,
+}
+
+impl TreeNode {
+    fn write(
+        &self,
+        out: &mut impl Write,
+        name: &str,
+        depth: usize,
+        total: i64,
+        min_percent: f64,
+    ) -> io::Result<()> {
+        writeln!(
+            out,
+            "{:>12} {:>6.2}%  {:indent$}{name}",
+            self.value,
+            percent(self.value, total),
+            "",
+            indent = depth * 2
+        )?;
+        let mut children: Vec<_> = self.children.iter().collect();
+        children.sort_by_key(|(_, child)| Reverse(child.value));
+        for (child_name, child) in children {
+            if percent(child.value, total) >= min_percent {
+                child.write(out, child_name, depth + 1, total, min_percent)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) fn tree(args: &Args) -> io::Result<()> {
+    let profile = load(single_file(args)?)?;
+    let index = value_index(&profile, args)?;
+    let mut root = TreeNode::default();
+    for sample in &profile.samples {
+        let value = sample.values.get(index).copied().unwrap_or_default();
+        root.value += value;
+        let mut node = &mut root;
+        for frame in sample.frames.iter().rev() {
+            node = node.children.entry(frame.short_function()).or_default();
+            node.value += value;
+        }
+    }
+
+    let mut out = io::stdout().lock();
+    writeln!(out, "{:>12} {:>7}  function", "inuse bytes", "inuse%")?;
+    root.write(&mut out, "all", 0, root.value, args.min_percent)
+}
+
+/// The values of a stack in the two dumps of `diff`.
+#[derive(Default)]
+struct Delta {
+    frames: Vec<Frame>,
+    base: i64,
+    new: i64,
+}
+
+pub(crate) fn diff(args: &Args) -> io::Result<()> {
+    let [base, new] = args.files.as_slice() else {
+        return Err(usage_error("diff takes the base and the new file"));
+    };
+    let (base, new) = (load(base)?, load(new)?);
+    let (base_index, new_index) = (value_index(&base, args)?, value_index(&new, args)?);
+
+    let mut deltas: HashMap<Vec<Frame>, Delta> = HashMap::new();
+    for (key, site) in sites(&base, base_index) {
+        let delta = deltas.entry(key).or_default();
+        delta.frames = site.frames;
+        delta.base = site.value;
+    }
+    for (key, site) in sites(&new, new_index) {
+        let delta = deltas.entry(key).or_default();
+        if delta.frames.is_empty() {
+            delta.frames = site.frames;
+        }
+        delta.new = site.value;
+    }
+    let mut deltas: Vec<_> = deltas
+        .into_values()
+        .filter(|delta| delta.base != delta.new)
+        .collect();
+    deltas.sort_by_key(|delta| Reverse((delta.new - delta.base).abs()));
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:>12} {:>12} {:>12}  site",
+        "delta bytes", "base bytes", "new bytes"
+    )?;
+    for delta in deltas.iter().take(args.n) {
+        writeln!(
+            out,
+            "{:>+12} {:>12} {:>12}  {}",
+            delta.new - delta.base,
+            delta.base,
+            delta.new,
+            site_name(&delta.frames)
+        )?;
+    }
+    let (base_total, new_total) = (base.total(base_index), new.total(new_index));
+    writeln!(
+        out,
+        "total inuse bytes: {base_total} -> {new_total} ({:+})",
+        new_total - base_total
+    )
+}
+
+pub(crate) fn merge(args: &Args) -> io::Result<()> {
+    let output = args
+        .output
+        .as_deref()
+        .ok_or_else(|| usage_error("merge needs the output file, -o OUT"))?;
+    if args.files.is_empty() {
+        return Err(usage_error("merge takes at least one file"));
+    }
+    let mut merger = ProfileMerger::new();
+    for path in &args.files {
+        let with_path = |err: io::Error| io::Error::new(err.kind(), format!("{path}: {err}"));
+        let data = fs::read(path).map_err(with_path)?;
+        // the samples are labelled with the file name, `pprof -tagfocus=source=...`.
+        let source = Path::new(path)
+            .file_name()
+            .map(|name| name.to_string_lossy());
+        merger
+            .add(&data, source.as_deref().filter(|_| args.source))
+            .map_err(with_path)?;
+    }
+    merger.write_to(BufWriter::new(File::create(output)?))
+}
+
+pub(crate) fn flamegraph(args: &Args) -> io::Result<()> {
+    let file = single_file(args)?;
+    let profile = load(file)?;
+    let index = value_index(&profile, args)?;
+    let output = match &args.output {
+        Some(output) => output.clone(),
+        None => Path::new(file)
+            .with_extension("svg")
+            .to_string_lossy()
+            .into_owned(),
+    };
+    write_profile_flamegraph(BufWriter::new(File::create(&output)?), &profile, index)?;
+    eprintln!("wrote {output}");
+    Ok(())
+}
+
+/// The old blocks of a stack.
+#[derive(Default)]
+struct Leak {
+    frames: Vec<Frame>,
+    bytes: i64,
+    blocks: i64,
+    oldest_ms: i64,
+}
+
+pub(crate) fn leaks(args: &Args) -> io::Result<()> {
+    let profile = load(single_file(args)?)?;
+    let index = value_index(&profile, args)?;
+    let blocks = profile.period.max(1);
+
+    let mut leaks: HashMap<Vec<Frame>, Leak> = HashMap::new();
+    let mut labelled = false;
+    for sample in &profile.samples {
+        let Some(age) = sample.num_label("age_ms") else {
+            continue;
+        };
+        labelled = true;
+        if age < args.min_age_ms {
+            continue;
+        }
+        let leak = leaks.entry(stack_key(&sample.frames)).or_default();
+        if leak.frames.is_empty() {
+            leak.frames = sample.frames.clone();
+        }
+        leak.bytes += sample.values.get(index).copied().unwrap_or_default();
+        leak.blocks += blocks;
+        leak.oldest_ms = leak.oldest_ms.max(age);
+    }
+    if !labelled {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "the profile has no age_ms labels, dump it with the labels captured",
+        ));
+    }
+    let mut leaks: Vec<_> = leaks.into_values().collect();
+    leaks.sort_by_key(|leak| Reverse(leak.bytes));
+
+    let mut out = io::stdout().lock();
+    writeln!(
+        out,
+        "{:>12} {:>9} {:>12}  site",
+        "bytes", "blocks", "oldest ms"
+    )?;
+    for leak in leaks.iter().take(args.n) {
+        writeln!(
+            out,
+            "{:>12} {:>9} {:>12}  {}",
+            leak.bytes,
+            leak.blocks,
+            leak.oldest_ms,
+            site_name(&leak.frames)
+        )?;
+    }
+    let total: i64 = leaks.iter().map(|leak| leak.bytes).sum();
+    writeln!(
+        out,
+        "bytes older than {} ms: {total} of {}",
+        args.min_age_ms,
+        profile.total(index)
+    )
+}
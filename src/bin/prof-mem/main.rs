@@ -0,0 +1,123 @@
+//! `prof-mem`, reads the `mem.*.pb` dumps of the profiler and reports on them.
+
+mod commands;
+
+use std::{env, io, process::ExitCode, str::FromStr};
+
+const USAGE: &str = "\
+usage: prof-mem <command> [options] <files>
+
+commands:
+  top [-n N] [--sort inuse|count|avg] FILE     the heaviest allocation sites
+  tree [--min-percent P] FILE                  the call tree of the in-use memory
+  diff [-n N] BASE NEW                         the sites that grew or shrank between two dumps
+  merge [--source] -o OUT FILE...              merge the dumps into one pprof file, --source
+                                               labels the samples with their file name
+  flamegraph [-o OUT] FILE                     render an SVG flamegraph, FILE.svg by default
+  leaks [--min-age MS] [-n N] FILE             the sites of the blocks older than MS (1000)
+
+options:
+  -i, --sample-index TYPE    the sample type to report, `space` or `inuse_space` by default
+";
+
+/// The command line, the options of all the commands share one struct.
+pub(crate) struct Args {
+    pub(crate) command: String,
+    pub(crate) files: Vec<String>,
+    pub(crate) n: usize,
+    pub(crate) sort: SortKey,
+    pub(crate) output: Option<String>,
+    pub(crate) sample_index: Option<String>,
+    pub(crate) min_percent: f64,
+    pub(crate) min_age_ms: i64,
+    pub(crate) source: bool,
+}
+
+/// The order of the `top` sites.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SortKey {
+    Inuse,
+    Count,
+    AvgSize,
+}
+
+pub(crate) fn usage_error(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, msg.into())
+}
+
+fn parse_value<T: FromStr>(flag: &str, value: Option<String>) -> io::Result<T> {
+    let value = value.ok_or_else(|| usage_error(format!("{flag} needs a value")))?;
+    value
+        .parse()
+        .map_err(|_| usage_error(format!("invalid value for {flag}: {value}")))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> io::Result<Args> {
+    let command = args.next().ok_or_else(|| usage_error("missing command"))?;
+    let mut parsed = Args {
+        command,
+        files: Vec::new(),
+        n: 20,
+        sort: SortKey::Inuse,
+        output: None,
+        sample_index: None,
+        min_percent: 1.0,
+        min_age_ms: 1000,
+        source: false,
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-n" => parsed.n = parse_value(&arg, args.next())?,
+            "--sort" => {
+                parsed.sort = match args.next().as_deref() {
+                    Some("inuse") => SortKey::Inuse,
+                    Some("count") => SortKey::Count,
+                    Some("avg") => SortKey::AvgSize,
+                    _ => return Err(usage_error("--sort is one of inuse, count, avg")),
+                }
+            }
+            "-o" | "--output" => parsed.output = Some(parse_value(&arg, args.next())?),
+            "-i" | "--sample-index" => parsed.sample_index = Some(parse_value(&arg, args.next())?),
+            "--min-percent" => parsed.min_percent = parse_value(&arg, args.next())?,
+            "--min-age" => parsed.min_age_ms = parse_value(&arg, args.next())?,
+            "--source" => parsed.source = true,
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(usage_error(format!("unknown option {arg}")));
+            }
+            _ => parsed.files.push(arg),
+        }
+    }
+    Ok(parsed)
+}
+
+fn run() -> io::Result<()> {
+    let args = parse_args(env::args().skip(1))?;
+    match args.command.as_str() {
+        "top" => commands::top(&args),
+        "tree" => commands::tree(&args),
+        "diff" => commands::diff(&args),
+        "merge" => commands::merge(&args),
+        "flamegraph" => commands::flamegraph(&args),
+        "leaks" => commands::leaks(&args),
+        "help" | "-h" | "--help" => {
+            print!("{USAGE}");
+            Ok(())
+        }
+        command => Err(usage_error(format!("unknown command {command}"))),
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        // the output went to a closed pipe, like `prof-mem top mem.pb | head`.
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("prof-mem: {err}");
+            if err.kind() == io::ErrorKind::InvalidInput {
+                eprint!("\n{USAGE}");
+            }
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use std::{collections::HashMap, ffi::c_void, fmt, panic};
+
+use crate::{
+    entry::AllocEntry,
+    frame::Frame,
+    profiler::{HeapProfiler, get_profiler},
+    stacks::StackId,
+};
+
+/// the violations kept for [`budget_violations`], the later ones are dropped.
+const MAX_LOGGED_VIOLATIONS: usize = 1024;
+
+/// A limit of the live bytes of the allocations made under a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Budget {
+    /// the stacks with a function whose name contains it are counted, e.g. `my_crate::cache`.
+    pub function: String,
+    pub limit_bytes: u64,
+}
+
+impl Budget {
+    pub fn new(function: impl Into<String>, limit_bytes: u64) -> Self {
+        Self {
+            function: function.into(),
+            limit_bytes,
+        }
+    }
+}
+
+/// A [`Budget`] going over its limit, reported once until it is back under it.
+#[derive(Clone, Debug)]
+pub struct BudgetViolation {
+    /// the index of the budget in the ones given to [`set_budgets`].
+    pub budget: usize,
+    pub function: String,
+    pub limit_bytes: u64,
+    pub live_bytes: u64,
+    /// the stack of the allocation crossing the limit, innermost first.
+    pub frames: Vec<Frame>,
+}
+
+impl fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "budget of `{}` exceeded: {} live bytes over {}",
+            self.function, self.live_bytes, self.limit_bytes
+        )?;
+        for frame in &self.frames {
+            writeln!(f, "    at {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The live bytes of a [`Budget`], see [`budget_usage`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BudgetUsage {
+    pub function: String,
+    pub limit_bytes: u64,
+    pub live_bytes: u64,
+    /// the highest live bytes since the budgets were set.
+    pub peak_bytes: u64,
+    pub violations: u64,
+}
+
+struct BudgetState {
+    budget: Budget,
+    live_bytes: u64,
+    peak_bytes: u64,
+    violations: u64,
+    over: bool,
+}
+
+/// The budgets and their counters, kept by `HeapProfiler::insert` and `free_block` under
+/// the profiler lock.
+pub(crate) struct BudgetTable {
+    budgets: Vec<BudgetState>,
+    /// the budgets each stack counts for, resolved at the first block of the stack.
+    verdicts: Option<HashMap<StackId, Box<[usize]>>>,
+    callback: Option<fn(&BudgetViolation)>,
+    violations: Vec<BudgetViolation>,
+}
+
+impl BudgetTable {
+    pub(crate) const fn new() -> Self {
+        Self {
+            budgets: Vec::new(),
+            verdicts: None,
+            callback: None,
+            violations: Vec::new(),
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_active(&self) -> bool {
+        !self.budgets.is_empty()
+    }
+
+    pub(crate) fn callback(&self) -> Option<fn(&BudgetViolation)> {
+        self.callback
+    }
+
+    pub(crate) fn reset(&mut self, budgets: Vec<Budget>) {
+        self.budgets = budgets
+            .into_iter()
+            .map(|budget| BudgetState {
+                budget,
+                live_bytes: 0,
+                peak_bytes: 0,
+                violations: 0,
+                over: false,
+            })
+            .collect();
+        self.verdicts = None;
+    }
+
+    fn verdict(
+        &mut self,
+        profiler: &HeapProfiler,
+        stack: StackId,
+        frames: &[*mut c_void],
+    ) -> &[usize] {
+        let budgets = &self.budgets;
+        self.verdicts
+            .get_or_insert_with(HashMap::new)
+            .entry(stack)
+            .or_insert_with(|| {
+                let symbols = profiler.resolve_frames(frames);
+                budgets
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, state)| {
+                        symbols
+                            .iter()
+                            .any(|symbol| symbol.name.contains(state.budget.function.as_str()))
+                    })
+                    .map(|(index, _)| index)
+                    .collect()
+            })
+    }
+
+    /// count `bytes` more for the budgets of the stack, the violations are returned
+    /// for [`notify`].
+    pub(crate) fn alloc(
+        &mut self,
+        profiler: &HeapProfiler,
+        stack: StackId,
+        frames: &[*mut c_void],
+        bytes: u64,
+    ) -> Vec<BudgetViolation> {
+        let mut violations = Vec::new();
+        let verdict = self.verdict(profiler, stack, frames).to_vec();
+        for index in verdict {
+            let state = &mut self.budgets[index];
+            state.live_bytes += bytes;
+            state.peak_bytes = state.peak_bytes.max(state.live_bytes);
+            if state.over || state.live_bytes <= state.budget.limit_bytes {
+                continue;
+            }
+            state.over = true;
+            state.violations += 1;
+            violations.push(BudgetViolation {
+                budget: index,
+                function: state.budget.function.clone(),
+                limit_bytes: state.budget.limit_bytes,
+                live_bytes: state.live_bytes,
+                frames: profiler
+                    .resolve_frames(frames)
+                    .iter()
+                    .map(Frame::from)
+                    .collect(),
+            });
+        }
+        let room = MAX_LOGGED_VIOLATIONS.saturating_sub(self.violations.len());
+        self.violations
+            .extend(violations.iter().take(room).cloned());
+        violations
+    }
+
+    /// count `bytes` less for the budgets of the stack, the stacks without a verdict
+    /// were never counted.
+    pub(crate) fn free(&mut self, stack: StackId, bytes: u64) {
+        let Some(verdict) = self
+            .verdicts
+            .as_ref()
+            .and_then(|verdicts| verdicts.get(&stack))
+        else {
+            return;
+        };
+        for index in verdict.iter() {
+            let state = &mut self.budgets[*index];
+            state.live_bytes = state.live_bytes.saturating_sub(bytes);
+            if state.live_bytes <= state.budget.limit_bytes {
+                state.over = false;
+            }
+        }
+    }
+
+    fn usage(&self) -> Vec<BudgetUsage> {
+        self.budgets
+            .iter()
+            .map(|state| BudgetUsage {
+                function: state.budget.function.clone(),
+                limit_bytes: state.budget.limit_bytes,
+                live_bytes: state.live_bytes,
+                peak_bytes: state.peak_bytes,
+                violations: state.violations,
+            })
+            .collect()
+    }
+}
+
+/// Call the callback of [`set_budget_callback`] at each violation, once the profiler
+/// lock is released.
+pub(crate) fn notify(profiler: &HeapProfiler, violations: &[BudgetViolation]) {
+    if violations.is_empty() {
+        return;
+    }
+    let _alloc_entry = AllocEntry::new();
+    let Some(callback) = profiler.with_budgets(|budgets| budgets.callback()) else {
+        return;
+    };
+    for violation in violations {
+        // the callback may run in the allocator, it must not unwind out of it.
+        let _ = panic::catch_unwind(|| callback(violation));
+    }
+}
+
+/// Set the budgets of the tracked blocks, replacing the previous ones, the live blocks
+/// are counted at once. The symbols of a stack are resolved at its first block and the
+/// verdict is kept, the values are scaled back by the sample rate.
+///
+/// ```
+/// use prof_mem::{Budget, ProfAlloc, ProfAllocConfig, budget_usage, set_budgets};
+///
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+///
+/// set_budgets([Budget::new("my_crate::cache", 512 << 20)]);
+/// assert_eq!(budget_usage()[0].live_bytes, 0);
+/// ```
+pub fn set_budgets(budgets: impl IntoIterator<Item = Budget>) {
+    let _alloc_entry = AllocEntry::new();
+    let profiler = get_profiler();
+    let violations = profiler.set_budgets(budgets.into_iter().collect());
+    notify(profiler, &violations);
+}
+
+/// Call `callback` at each [`BudgetViolation`], `None` removes it.
+///
+/// The callback runs in the allocator of the block crossing the limit, after the profiler
+/// lock is released. The allocations it makes are not tracked and a panic is caught and
+/// dropped, it should be short, e.g. log or bump a counter.
+pub fn set_budget_callback(callback: Option<fn(&BudgetViolation)>) {
+    let _alloc_entry = AllocEntry::new();
+    get_profiler().with_budgets(|budgets| budgets.callback = callback);
+}
+
+/// The live bytes of the budgets, in the order of [`set_budgets`].
+pub fn budget_usage() -> Vec<BudgetUsage> {
+    let _alloc_entry = AllocEntry::new();
+    get_profiler().with_budgets(|budgets| budgets.usage())
+}
+
+/// Take the violations since the last call, the first 1024 are kept.
+pub fn budget_violations() -> Vec<BudgetViolation> {
+    let _alloc_entry = AllocEntry::new();
+    get_profiler().with_budgets(|budgets| std::mem::take(&mut budgets.violations))
+}
@@ -0,0 +1,114 @@
+use crate::profiler::DEFAULT_SKIP_PREFIXES;
+
+/// The profiler configuration, built in const context for the `#[global_allocator]` static.
+///
+/// ```
+/// use prof_mem::{ProfAlloc, ProfAllocConfig};
+///
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().max_depth(64).min_size(256));
+/// ```
+///
+/// The config of the allocator is applied once, at the first allocation going through it.
+#[derive(Clone, Copy, Debug)]
+pub struct ProfAllocConfig {
+    pub(crate) max_depth: usize,
+    pub(crate) sample_rate: usize,
+    pub(crate) enabled: bool,
+    pub(crate) min_size: usize,
+    pub(crate) max_size: usize,
+    pub(crate) capture_labels: bool,
+    pub(crate) size_labels: bool,
+    pub(crate) skip_prefixes: &'static [&'static str],
+}
+
+impl ProfAllocConfig {
+    /// 128 frames per stack, every allocation tracked from the start, with labels.
+    pub const fn new() -> Self {
+        Self {
+            max_depth: 128,
+            sample_rate: 1,
+            enabled: true,
+            min_size: 0,
+            max_size: usize::MAX,
+            capture_labels: true,
+            size_labels: false,
+            skip_prefixes: DEFAULT_SKIP_PREFIXES,
+        }
+    }
+
+    /// the max frames kept per stack.
+    pub const fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// track one of every `sample_rate` allocations, the dumped values are scaled back by the rate.
+    /// 0 is taken as 1.
+    pub const fn sample_rate(mut self, sample_rate: usize) -> Self {
+        self.sample_rate = if sample_rate == 0 { 1 } else { sample_rate };
+        self
+    }
+
+    /// whether tracking is on at start, see [`set_enabled`](crate::set_enabled).
+    pub const fn enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// allocations smaller than `min_size` bytes are not tracked.
+    pub const fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// allocations larger than `max_size` bytes are not tracked.
+    pub const fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size;
+        self
+    }
+
+    /// only the allocations of `min_size..=max_size` bytes are tracked.
+    pub const fn size_range(self, min_size: usize, max_size: usize) -> Self {
+        self.min_size(min_size).max_size(max_size)
+    }
+
+    /// whether the samples carry labels, the `alloc` address and the `age_ms` of the block.
+    pub const fn capture_labels(mut self, capture_labels: bool) -> Self {
+        self.capture_labels = capture_labels;
+        self
+    }
+
+    /// whether the samples carry a numeric `bytes` label of the block size,
+    /// so `pprof -tagfocus=bytes=1kb:` selects by size class.
+    pub const fn size_labels(mut self, size_labels: bool) -> Self {
+        self.size_labels = size_labels;
+        self
+    }
+
+    /// the symbol prefixes of the allocation machinery dropped from the top of the stacks,
+    /// [`DEFAULT_SKIP_PREFIXES`] by default.
+    pub const fn skip_prefixes(mut self, skip_prefixes: &'static [&'static str]) -> Self {
+        self.skip_prefixes = skip_prefixes;
+        self
+    }
+}
+
+impl ProfAllocConfig {
+    /// whether the allocations of `size` bytes pass the size filter.
+    #[inline(always)]
+    pub(crate) const fn tracks_size(&self, size: usize) -> bool {
+        size >= self.min_size && size <= self.max_size
+    }
+
+    #[inline(always)]
+    pub(crate) const fn has_size_filter(&self) -> bool {
+        self.min_size > 0 || self.max_size < usize::MAX
+    }
+}
+
+impl Default for ProfAllocConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
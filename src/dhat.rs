@@ -0,0 +1,79 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{json::JsonStr, process, profiler::get_profiler};
+
+/// Write the allocation sites in the `dhat-heap.json` format of DHAT, viewable in `dh_view.html`.
+///
+/// The times are in microseconds since the profiler start.
+pub(crate) fn write_dhat<W: Write>(mut writer: W) -> io::Result<()> {
+    let profiler = get_profiler();
+    let _guard = profiler.lock();
+    let sites = profiler.resolved_sites();
+    let global = profiler.global_stats();
+    let now = profiler.uptime().as_nanos() as u64;
+    let scale = profiler.config().sample_rate as u64;
+
+    // the frame table, "[root]" first as dh_view expects.
+    let mut ftbl = vec!["[root]".to_string()];
+    let mut ftbl_index: HashMap<String, usize> = HashMap::new();
+
+    write!(
+        writer,
+        "{{\"dhatFileVersion\":2,\"mode\":\"rust-heap\",\"verb\":\"Allocated\",\
+         \"bklt\":true,\"bkacc\":false,\"tu\":\"µs\",\"Mtu\":\"s\",\"tuth\":10,\
+         \"cmd\":{},\"pid\":{},\"te\":{},\"tg\":{},\n\"pps\":[",
+        JsonStr(&process::command_line()),
+        std::process::id(),
+        now / 1000,
+        global.max_time / 1000,
+    )?;
+    let mut first = true;
+    for site in sites.iter().filter(|site| site.stats.total_blocks > 0) {
+        let stats = &site.stats;
+        let fs: Vec<String> = site
+            .frames
+            .iter()
+            .map(|frame| {
+                let desc = format!(
+                    "{:#x}: {} ({}:{}:{})",
+                    frame.addr as usize, frame.name, frame.file_name, frame.line_no, frame.col_no
+                );
+                let idx = *ftbl_index.entry(desc).or_insert_with_key(|desc| {
+                    ftbl.push(desc.clone());
+                    ftbl.len() - 1
+                });
+                idx.to_string()
+            })
+            .collect();
+        if !first {
+            writer.write_all(b",")?;
+        }
+        first = false;
+        write!(
+            writer,
+            "\n{{\"tb\":{},\"tbk\":{},\"tl\":{},\"mb\":{},\"mbk\":{},\"gb\":{},\"gbk\":{},\"eb\":{},\"ebk\":{},\"fs\":[{}]}}",
+            stats.total_bytes.saturating_mul(scale),
+            stats.total_blocks.saturating_mul(scale),
+            stats.total_lifetime(now) * scale as u128 / 1000,
+            stats.max_bytes.saturating_mul(scale),
+            stats.max_blocks.saturating_mul(scale),
+            stats.gmax_bytes.saturating_mul(scale),
+            stats.gmax_blocks.saturating_mul(scale),
+            stats.live_bytes.saturating_mul(scale),
+            stats.live_blocks.saturating_mul(scale),
+            fs.join(","),
+        )?;
+    }
+    writer.write_all(b"\n],\n\"ftbl\":[")?;
+    for (idx, frame) in ftbl.iter().enumerate() {
+        if idx > 0 {
+            writer.write_all(b",")?;
+        }
+        write!(writer, "\n{}", JsonStr(frame))?;
+    }
+    writer.write_all(b"\n]\n}\n")?;
+    writer.flush()
+}
@@ -0,0 +1,137 @@
+use std::{
+    io::{self, Write},
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    dhat::write_dhat,
+    entry::AllocEntry,
+    folded::FoldedWriter,
+    json_writer::write_json,
+    profile_proto::ProfileProtoWriter,
+    profiler::{SymbolFramesWriter, get_profiler},
+};
+
+/// The output formats of a dump.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// the pprof protobuf, for `pprof` or `go tool pprof`.
+    #[default]
+    Pprof,
+    /// the folded stacks of the flamegraph tools, in-use bytes per stack.
+    Folded,
+    /// the `dhat-heap.json` of DHAT, per-site totals, peaks and lifetimes for `dh_view.html`.
+    Dhat,
+    /// JSON lines, the first line describes the profile, each following line is one allocation site:
+    ///
+    /// ```text
+    /// {"type":"profile","schema":1,"version":"0.1.0","time_nanos":1760000000000000000,
+    ///  "duration_nanos":1500000000,"pid":42,"sample_rate":1,"comments":["pid: 42",...]}
+    /// {"type":"stack","id":3,"inuse_bytes":4096,"inuse_blocks":4,"alloc_bytes":8192,"alloc_blocks":8,
+    ///  "labels":{"age_ms":1200,"bytes":1024},
+    ///  "frames":[{"function":"my_crate::load","file":"src/load.rs","line":12,"address":"0x55d0c1a2b3c4"},...]}
+    /// ```
+    ///
+    /// - `inuse_*` count the live blocks, `alloc_*` all the blocks allocated since the start,
+    ///   both scaled back by the sample rate.
+    /// - `labels` are the numeric labels of the site: `age_ms` the age of its oldest live block
+    ///   and `bytes` the average size of its live blocks, absent without live blocks.
+    /// - `frames` are innermost first, the inlined functions get their own frame.
+    ///
+    /// New fields may be added within a schema version, the existing ones keep their meaning.
+    Json,
+}
+
+impl DumpFormat {
+    /// the file extension of the format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DumpFormat::Pprof => "pb",
+            DumpFormat::Folded => "folded",
+            DumpFormat::Dhat => "dhat.json",
+            DumpFormat::Json => "jsonl",
+        }
+    }
+}
+
+/// The options of [`dump_with`] and [`dump_to_with`].
+#[derive(Clone, Debug, Default)]
+pub struct DumpOptions {
+    format: DumpFormat,
+    path: Option<PathBuf>,
+}
+
+impl DumpOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn format(mut self, format: DumpFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// the file of the dump, `mem.<millis>.<extension>` in the current directory by default.
+    pub fn path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+}
+
+/// Dump the live allocations to `mem.<millis>.pb` in the current directory.
+pub fn dump() -> io::Result<()> {
+    dump_with(&DumpOptions::new()).map(|_| ())
+}
+
+/// Dump the live allocations to the file of the options, returns the path written.
+pub fn dump_with(options: &DumpOptions) -> io::Result<PathBuf> {
+    let _alloc_entry = AllocEntry::new();
+    let path = match &options.path {
+        Some(path) => path.clone(),
+        None => {
+            let time = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| io::Error::other(e.to_string()))?;
+            PathBuf::from(format!(
+                "mem.{}.{}",
+                time.as_millis(),
+                options.format.extension()
+            ))
+        }
+    };
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(true)
+        .write(true)
+        .open(&path)?;
+    dump_to_with(&mut file, options)?;
+    Ok(path)
+}
+
+/// Dump the live allocations as a pprof protobuf into the writer.
+pub fn dump_to<W: Write>(writer: W) -> io::Result<()> {
+    dump_to_with(writer, &DumpOptions::new())
+}
+
+/// Dump the live allocations in the format of the options into the writer.
+pub fn dump_to_with<W: Write>(writer: W, options: &DumpOptions) -> io::Result<()> {
+    let _alloc_entry = AllocEntry::new();
+    let profiler = get_profiler();
+    let config = profiler.config();
+    match options.format {
+        DumpFormat::Pprof => {
+            let mut writer = ProfileProtoWriter::new(writer, &config);
+            writer.set_duration(profiler.uptime());
+            write_dump(writer)
+        }
+        DumpFormat::Folded => write_dump(FoldedWriter::new(writer, &config)),
+        DumpFormat::Dhat => write_dhat(writer),
+        DumpFormat::Json => write_json(writer),
+    }
+}
+
+fn write_dump<W: SymbolFramesWriter>(mut writer: W) -> io::Result<()> {
+    get_profiler().write_symbol_frames(&mut writer)?;
+    writer.flush()
+}
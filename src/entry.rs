@@ -1,7 +1,16 @@
-use std::cell::Cell;
+use std::{
+    cell::{Cell, RefCell},
+    ffi::c_void,
+};
 
 thread_local! {
-    static ALLOC_ENTRY: Cell<usize> = Cell::new(0);
+    static ALLOC_ENTRY: Cell<usize> = const { Cell::new(0) };
+    // the `measure` scopes open on the thread, the counters only move inside one.
+    static MEASURE_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static THREAD_COUNTERS: Cell<ThreadCounters> = const { Cell::new(ThreadCounters::new()) };
+    // the `forbid_alloc` guards alive on the thread and the allocations they caught.
+    static FORBID_DEPTH: Cell<usize> = const { Cell::new(0) };
+    static FORBIDDEN: RefCell<Vec<ForbiddenRecord>> = const { RefCell::new(Vec::new()) };
 }
 
 pub(crate) struct AllocEntry(pub(crate) usize);
@@ -25,3 +34,125 @@ impl Drop for AllocEntry {
         ALLOC_ENTRY.with(|allc_entry| allc_entry.set(allc_entry.get() - 1));
     }
 }
+
+/// The allocator calls of the thread during the `measure` scopes, the allocations of the
+/// profiler itself are left out.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct ThreadCounters {
+    pub(crate) allocs: u64,
+    pub(crate) deallocs: u64,
+    pub(crate) reallocs: u64,
+    pub(crate) allocated_bytes: u64,
+    pub(crate) freed_bytes: u64,
+}
+
+impl ThreadCounters {
+    const fn new() -> Self {
+        Self {
+            allocs: 0,
+            deallocs: 0,
+            reallocs: 0,
+            allocated_bytes: 0,
+            freed_bytes: 0,
+        }
+    }
+
+    pub(crate) fn current() -> Self {
+        THREAD_COUNTERS.get()
+    }
+
+    #[inline(always)]
+    fn update(f: impl FnOnce(&mut ThreadCounters)) {
+        if MEASURE_DEPTH.get() == 0 || ALLOC_ENTRY.get() != 0 {
+            return;
+        }
+        let mut counters = THREAD_COUNTERS.get();
+        f(&mut counters);
+        THREAD_COUNTERS.set(counters);
+    }
+
+    #[inline(always)]
+    pub(crate) fn alloc(size: usize) {
+        Self::update(|c| {
+            c.allocs += 1;
+            c.allocated_bytes += size as u64;
+        });
+    }
+
+    #[inline(always)]
+    pub(crate) fn dealloc(size: usize) {
+        Self::update(|c| {
+            c.deallocs += 1;
+            c.freed_bytes += size as u64;
+        });
+    }
+
+    #[inline(always)]
+    pub(crate) fn realloc(old_size: usize, new_size: usize) {
+        Self::update(|c| {
+            c.reallocs += 1;
+            c.freed_bytes += old_size as u64;
+            c.allocated_bytes += new_size as u64;
+        });
+    }
+}
+
+/// A `measure` scope of the thread, closed on drop even if the measured code panics.
+pub(crate) struct MeasureScope(());
+
+impl MeasureScope {
+    pub(crate) fn new() -> Self {
+        MEASURE_DEPTH.set(MEASURE_DEPTH.get() + 1);
+        Self(())
+    }
+}
+
+impl Drop for MeasureScope {
+    fn drop(&mut self) {
+        MEASURE_DEPTH.set(MEASURE_DEPTH.get() - 1);
+    }
+}
+
+/// An allocation made under a `forbid_alloc` guard, the stack is resolved when the
+/// guard drops, never inside the allocator.
+pub(crate) struct ForbiddenRecord {
+    pub(crate) size: usize,
+    pub(crate) frames: Vec<*mut c_void>,
+}
+
+/// A `forbid_alloc` scope of the thread.
+pub(crate) struct ForbidScope {
+    /// the count of the records before the scope opened.
+    start: usize,
+}
+
+impl ForbidScope {
+    pub(crate) fn new() -> Self {
+        FORBID_DEPTH.set(FORBID_DEPTH.get() + 1);
+        Self {
+            start: FORBIDDEN.with_borrow(|records| records.len()),
+        }
+    }
+
+    /// whether the allocation at hand is forbidden, the ones of the profiler itself are not.
+    #[inline(always)]
+    pub(crate) fn forbids() -> bool {
+        FORBID_DEPTH.get() != 0 && ALLOC_ENTRY.get() == 0
+    }
+
+    /// record an allocation, called by the allocator with an `AllocEntry` held.
+    pub(crate) fn record(record: ForbiddenRecord) {
+        FORBIDDEN.with_borrow_mut(|records| records.push(record));
+    }
+
+    /// the allocations caught since the scope opened, taken out of the thread records.
+    pub(crate) fn take(&self) -> Vec<ForbiddenRecord> {
+        FORBIDDEN.with_borrow_mut(|records| records.split_off(self.start.min(records.len())))
+    }
+}
+
+impl Drop for ForbidScope {
+    fn drop(&mut self) {
+        FORBID_DEPTH.set(FORBID_DEPTH.get() - 1);
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    collections::HashMap,
+    ffi::c_void,
+    fmt,
+    sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use crate::{
+    entry::AllocEntry,
+    frame::Frame,
+    profiler::{HeapProfiler, get_profiler},
+};
+
+/// the injected failures kept for [`injected_faults`], the later ones are dropped.
+const MAX_LOGGED_FAULTS: usize = 1024;
+
+/// A fault injection rule, the allocations meeting all its conditions return null.
+///
+/// ```
+/// use prof_mem::FaultRule;
+///
+/// // one of every 10 allocations of 1 MiB or more made under `parse_chunk`.
+/// let rule = FaultRule::new().min_size(1 << 20).in_function("parse_chunk").every(10);
+/// ```
+#[derive(Clone, Debug)]
+pub struct FaultRule {
+    every: u64,
+    probability: f64,
+    min_size: usize,
+    function: Option<String>,
+}
+
+impl FaultRule {
+    /// a rule failing every allocation, narrowed by the other methods.
+    pub fn new() -> Self {
+        Self {
+            every: 1,
+            probability: 1.0,
+            min_size: 0,
+            function: None,
+        }
+    }
+
+    /// fail one of every `n` allocations meeting the other conditions, 0 is taken as 1.
+    pub fn every(mut self, n: u64) -> Self {
+        self.every = n.max(1);
+        self
+    }
+
+    /// fail the allocations meeting the other conditions with the `probability`.
+    pub fn probability(mut self, probability: f64) -> Self {
+        self.probability = probability.clamp(0.0, 1.0);
+        self
+    }
+
+    /// only the allocations of `min_size` bytes or more fail.
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// only the allocations whose stack has a function whose name contains `function` fail,
+    /// e.g. `my_crate::parser`, the stack is captured and resolved for each candidate.
+    pub fn in_function(mut self, function: impl Into<String>) -> Self {
+        self.function = Some(function.into());
+        self
+    }
+}
+
+impl Default for FaultRule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An allocation failed on purpose by a [`FaultRule`].
+#[derive(Clone, Debug)]
+pub struct InjectedFault {
+    pub size: usize,
+    /// the index of the rule in the ones given to [`inject_faults`].
+    pub rule: usize,
+    /// the stack of the allocation, innermost first.
+    pub frames: Vec<Frame>,
+}
+
+impl fmt::Display for InjectedFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "injected failure of a {} bytes allocation, rule {}",
+            self.size, self.rule
+        )?;
+        for frame in &self.frames {
+            writeln!(f, "    at {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+struct RuleState {
+    rule: FaultRule,
+    /// the allocations meeting the size and the stack conditions.
+    count: u64,
+    /// whether the frame at the address is in the function of the rule.
+    frames: HashMap<usize, bool>,
+}
+
+impl RuleState {
+    fn in_function(&mut self, profiler: &HeapProfiler, stack: &[*mut c_void]) -> bool {
+        let Some(function) = &self.rule.function else {
+            return true;
+        };
+        stack.iter().any(|addr| {
+            *self.frames.entry(*addr as usize).or_insert_with(|| {
+                profiler
+                    .resolve_frames(&[*addr])
+                    .iter()
+                    .any(|symbol| symbol.name.contains(function.as_str()))
+            })
+        })
+    }
+}
+
+struct FaultLog {
+    rule: usize,
+    size: usize,
+    frames: Vec<*mut c_void>,
+}
+
+struct FaultState {
+    rules: Vec<RuleState>,
+    /// the splitmix64 state of the probabilities.
+    rng: u64,
+    log: Vec<FaultLog>,
+}
+
+// the frames of a `FaultLog` are only looked up by `resolve_frames` under the profiler lock.
+unsafe impl Send for FaultState {}
+
+pub(crate) struct Faults {
+    /// the smallest `min_size` of the rules, `usize::MAX` without rules, the allocations
+    /// below it are let through without a lock.
+    min_size: AtomicUsize,
+    state: Mutex<FaultState>,
+}
+
+pub(crate) static FAULTS: Faults = Faults {
+    min_size: AtomicUsize::new(usize::MAX),
+    state: Mutex::new(FaultState {
+        rules: Vec::new(),
+        rng: 0,
+        log: Vec::new(),
+    }),
+};
+
+impl Faults {
+    /// whether a rule may fail the allocation of `size` bytes, see [`Faults::check`].
+    #[inline(always)]
+    pub(crate) fn may_fail(&self, size: usize) -> bool {
+        size >= self.min_size.load(Ordering::Relaxed)
+    }
+
+    /// whether the allocation of `size` bytes must fail, called by the allocator with the top
+    /// `AllocEntry` held.
+    pub(crate) fn check(&self, profiler: &HeapProfiler, size: usize) -> bool {
+        // the profiler lock first, the stacks are captured under it.
+        let _guard = profiler.lock();
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        let FaultState { rules, rng, log } = &mut *state;
+        let mut stack = None;
+        for (index, rule) in rules.iter_mut().enumerate() {
+            if size < rule.rule.min_size {
+                continue;
+            }
+            if rule.rule.function.is_some() {
+                let stack = stack.get_or_insert_with(|| profiler.trace_frames());
+                if !rule.in_function(profiler, stack) {
+                    continue;
+                }
+            }
+            rule.count += 1;
+            if rule.count % rule.rule.every != 0 {
+                continue;
+            }
+            if rule.rule.probability < 1.0 && next_f64(rng) >= rule.rule.probability {
+                continue;
+            }
+            if log.len() < MAX_LOGGED_FAULTS {
+                log.push(FaultLog {
+                    rule: index,
+                    size,
+                    frames: stack.unwrap_or_else(|| profiler.trace_frames()),
+                });
+            }
+            return true;
+        }
+        false
+    }
+}
+
+/// a uniform float of `[0, 1)`.
+fn next_f64(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Make the allocations of every thread fail by the `rules`, the first rule an allocation
+/// meets fails it. Replaces the rules in place and restarts their counters, an empty
+/// list turns the injection off.
+///
+/// ```
+/// use prof_mem::{FaultRule, ProfAlloc, ProfAllocConfig, clear_faults, inject_faults};
+///
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().enabled(false));
+///
+/// inject_faults([FaultRule::new().min_size(1 << 20).every(2)]);
+/// let mut v = Vec::<u8>::new();
+/// assert!(v.try_reserve(1 << 20).is_ok());
+/// let mut w = Vec::<u8>::new();
+/// assert!(w.try_reserve(1 << 20).is_err());
+/// clear_faults();
+/// ```
+pub fn inject_faults(rules: impl IntoIterator<Item = FaultRule>) {
+    let _alloc_entry = AllocEntry::new();
+    let _guard = get_profiler().lock();
+    let mut state = FAULTS.state.lock().unwrap_or_else(PoisonError::into_inner);
+    state.rules = rules
+        .into_iter()
+        .map(|rule| RuleState {
+            rule,
+            count: 0,
+            frames: HashMap::new(),
+        })
+        .collect();
+    state.rng = 0;
+    let min_size = state.rules.iter().map(|rule| rule.rule.min_size).min();
+    FAULTS
+        .min_size
+        .store(min_size.unwrap_or(usize::MAX), Ordering::Relaxed);
+}
+
+/// Stop the fault injection, the failures already injected stay in the log.
+pub fn clear_faults() {
+    inject_faults([]);
+}
+
+/// Take the log of the injected failures, with their stacks, the first 1024 since the
+/// last call are kept.
+pub fn injected_faults() -> Vec<InjectedFault> {
+    let _alloc_entry = AllocEntry::new();
+    let profiler = get_profiler();
+    let _guard = profiler.lock();
+    let log = {
+        let mut state = FAULTS.state.lock().unwrap_or_else(PoisonError::into_inner);
+        std::mem::take(&mut state.log)
+    };
+    log.into_iter()
+        .map(|fault| InjectedFault {
+            size: fault.size,
+            rule: fault.rule,
+            frames: profiler
+                .resolve_frames(&fault.frames)
+                .iter()
+                .map(Frame::from)
+                .collect(),
+        })
+        .collect()
+}
@@ -0,0 +1,293 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use crate::{entry::AllocEntry, frame::short_name, pprof::HeapProfile, profiler::get_profiler};
+
+const WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+const HEADER: f64 = 40.0;
+// the approximate width of a character of the 12px monospace font.
+const CHAR_WIDTH: f64 = 7.2;
+
+/// The value of the frames of a flamegraph.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlamegraphMetric {
+    /// the bytes of the live blocks.
+    #[default]
+    InuseBytes,
+    /// the blocks allocated since the start, freed or not.
+    Allocations,
+}
+
+impl FlamegraphMetric {
+    fn title(&self) -> &'static str {
+        match self {
+            FlamegraphMetric::InuseBytes => "In-use bytes",
+            FlamegraphMetric::Allocations => "Allocations",
+        }
+    }
+
+    fn unit(&self) -> &'static str {
+        match self {
+            FlamegraphMetric::InuseBytes => "bytes",
+            FlamegraphMetric::Allocations => "allocations",
+        }
+    }
+}
+
+#[derive(Default)]
+struct Node {
+    value: u64,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn add(&mut self, stack: &[String], value: u64) {
+        self.value += value;
+        if let Some((name, rest)) = stack.split_first() {
+            self.children
+                .entry(name.clone())
+                .or_default()
+                .add(rest, value);
+        }
+    }
+
+    fn depth(&self) -> usize {
+        self.children
+            .values()
+            .map(|c| c.depth() + 1)
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// the crate of the function, the first path segment of its name or of its self type.
+fn crate_of(name: &str) -> &str {
+    let name = name.trim_start_matches('<').trim_start_matches('&');
+    let end = name.find(['[', ':', '<', ' ']).unwrap_or(name.len());
+    &name[..end]
+}
+
+/// a warm color per crate, stable across dumps.
+fn crate_color(name: &str) -> String {
+    let hash = crate_of(name)
+        .bytes()
+        .fold(2166136261u32, |h, b| (h ^ b as u32).wrapping_mul(16777619));
+    let r = 205 + hash % 50;
+    let g = 80 + (hash >> 8) % 150;
+    let b = (hash >> 16) % 60;
+    format!("rgb({r},{g},{b})")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+struct Renderer<'a> {
+    total: u64,
+    height: f64,
+    unit: &'a str,
+    svg: String,
+}
+
+impl Renderer<'_> {
+    fn frame(&mut self, name: &str, node: &Node, x: u64, depth: usize) {
+        let fx = x as f64 / self.total as f64;
+        let fw = node.value as f64 / self.total as f64;
+        // too narrow to be seen.
+        if fw * WIDTH < 0.1 {
+            return;
+        }
+        let y = self.height - FRAME_HEIGHT * (depth as f64 + 1.0);
+        let short = short_name(name);
+        let _ = write!(
+            self.svg,
+            r#"<g class="f" data-x="{fx}" data-w="{fw}" data-n="{name}"><title>{name} ({value} {unit}, {percent:.2}%)</title><rect x="{x:.1}" y="{y:.1}" width="{w:.1}" height="{h:.1}" fill="{color}" rx="2"/><text x="{tx:.1}" y="{ty:.1}">{text}</text></g>"#,
+            name = escape(&short),
+            value = node.value,
+            unit = self.unit,
+            percent = fw * 100.0,
+            x = fx * WIDTH,
+            w = fw * WIDTH,
+            h = FRAME_HEIGHT - 1.0,
+            color = crate_color(name),
+            tx = fx * WIDTH + 3.0,
+            ty = y + FRAME_HEIGHT - 4.0,
+            text = escape(&fit(&short, fw * WIDTH)),
+        );
+        self.svg.push('\n');
+        let mut child_x = x;
+        for (child_name, child) in &node.children {
+            self.frame(child_name, child, child_x, depth + 1);
+            child_x += child.value;
+        }
+    }
+}
+
+/// the label fitting in `width` pixels.
+fn fit(name: &str, width: f64) -> String {
+    let chars = ((width - 6.0) / CHAR_WIDTH) as usize;
+    if chars < 3 {
+        String::new()
+    } else if name.chars().count() <= chars {
+        name.to_string()
+    } else {
+        let mut fitted: String = name.chars().take(chars - 2).collect();
+        fitted.push_str("..");
+        fitted
+    }
+}
+
+const SCRIPT: &str = r#"
+var W = 1200, frames = document.querySelectorAll("g.f");
+function fit(name, w) {
+  var n = Math.floor((w - 6) / 7.2);
+  if (n < 3) return "";
+  return name.length <= n ? name : name.substring(0, n - 2) + "..";
+}
+function place(g, x, w) {
+  var r = g.querySelector("rect"), t = g.querySelector("text");
+  g.style.display = w * W < 0.1 ? "none" : "";
+  r.setAttribute("x", x * W); r.setAttribute("width", w * W);
+  t.setAttribute("x", x * W + 3); t.textContent = fit(g.dataset.n, w * W);
+}
+function zoom(g) {
+  var zx = +g.dataset.x, zw = +g.dataset.w, zy = +g.querySelector("rect").getAttribute("y");
+  frames.forEach(function (f) {
+    var x = +f.dataset.x, w = +f.dataset.w, y = +f.querySelector("rect").getAttribute("y");
+    var l = Math.max(x, zx), r = Math.min(x + w, zx + zw);
+    if (r <= l || (y > zy && !(x <= zx && x + w >= zx + zw))) { f.style.display = "none"; return; }
+    place(f, (l - zx) / zw, (r - l) / zw);
+  });
+  document.getElementById("reset").style.display = "";
+}
+function reset() {
+  frames.forEach(function (f) { place(f, +f.dataset.x, +f.dataset.w); });
+  document.getElementById("reset").style.display = "none";
+}
+function search() {
+  var term = prompt("Search frames (regular expression)");
+  var matched = document.getElementById("matched");
+  frames.forEach(function (f) { f.classList.remove("hit"); });
+  matched.textContent = "";
+  if (!term) return;
+  var re = new RegExp(term), spans = [];
+  frames.forEach(function (f) {
+    if (!re.test(f.dataset.n)) return;
+    f.classList.add("hit");
+    spans.push([+f.dataset.x, +f.dataset.x + +f.dataset.w]);
+  });
+  // nested matches count once.
+  spans.sort(function (a, b) { return a[0] - b[0]; });
+  var total = 0, end = 0;
+  spans.forEach(function (s) { if (s[0] >= end) { total += s[1] - s[0]; end = s[1]; } else if (s[1] > end) { total += s[1] - end; end = s[1]; } });
+  matched.textContent = "Matched: " + (total * 100).toFixed(2) + "%";
+}
+frames.forEach(function (f) { f.addEventListener("click", function () { zoom(f); }); });
+document.getElementById("reset").addEventListener("click", reset);
+document.getElementById("search").addEventListener("click", search);
+"#;
+
+/// Render the aggregated stacks of the profiler as an interactive SVG flamegraph.
+pub fn write_flamegraph<W: Write>(writer: W, metric: FlamegraphMetric) -> io::Result<()> {
+    let _alloc_entry = AllocEntry::new();
+    let profiler = get_profiler();
+    let scale = profiler.config().sample_rate as u64;
+    let mut root = Node::default();
+    for site in profiler.resolved_sites() {
+        let value = match metric {
+            FlamegraphMetric::InuseBytes => site.stats.live_bytes,
+            FlamegraphMetric::Allocations => site.stats.total_blocks,
+        }
+        .saturating_mul(scale);
+        if value == 0 {
+            continue;
+        }
+        let stack: Vec<_> = site.frames.iter().rev().map(|f| f.name.clone()).collect();
+        root.add(&stack, value);
+    }
+    render(writer, &root, metric.title(), metric.unit())
+}
+
+/// Render the values at `sample_index` of a profile read back with [`HeapProfile`], the
+/// negative values of a diff are left out.
+pub fn write_profile_flamegraph<W: Write>(
+    writer: W,
+    profile: &HeapProfile,
+    sample_index: usize,
+) -> io::Result<()> {
+    let mut root = Node::default();
+    for sample in &profile.samples {
+        let value = sample.values.get(sample_index).copied().unwrap_or_default();
+        if value <= 0 {
+            continue;
+        }
+        let stack: Vec<_> = sample
+            .frames
+            .iter()
+            .rev()
+            .map(|f| f.function.clone())
+            .collect();
+        root.add(&stack, value as u64);
+    }
+    let (title, unit) = profile
+        .sample_types
+        .get(sample_index)
+        .map(|t| (t.kind.as_str(), t.unit.as_str()))
+        .unwrap_or_default();
+    render(writer, &root, title, unit)
+}
+
+fn render<W: Write>(mut writer: W, root: &Node, title: &str, unit: &str) -> io::Result<()> {
+    let height = HEADER + FRAME_HEIGHT * (root.depth() as f64 + 1.0) + 10.0;
+    let mut renderer = Renderer {
+        total: root.value.max(1),
+        height: height - 10.0,
+        unit,
+        svg: String::new(),
+    };
+    renderer.frame("all", root, 0, 0);
+
+    write!(
+        writer,
+        r##"<?xml version="1.0" standalone="no"?>
+<svg version="1.1" width="{WIDTH}" height="{height}" viewBox="0 0 {WIDTH} {height}" xmlns="http://www.w3.org/2000/svg">
+<style>
+text {{ font-family: monospace; font-size: 12px; fill: #000; pointer-events: none; }}
+g.f {{ cursor: pointer; }}
+g.f:hover rect {{ stroke: #000; stroke-width: 0.5; }}
+g.hit rect {{ fill: rgb(230,0,230); }}
+.ctl {{ cursor: pointer; fill: #333; pointer-events: all; }}
+</style>
+<rect width="100%" height="100%" fill="#eeeeee"/>
+<text x="{center}" y="20" text-anchor="middle" style="font-size:16px">{title} ({total} {unit})</text>
+<text id="reset" class="ctl" x="10" y="20" style="display:none">Reset Zoom</text>
+<text id="search" class="ctl" x="{search_x}" y="20">Search</text>
+<text id="matched" x="{search_x}" y="34"></text>
+{frames}<script><![CDATA[{SCRIPT}]]></script>
+</svg>
+"##,
+        center = WIDTH / 2.0,
+        search_x = WIDTH - 110.0,
+        title = escape(title),
+        total = root.value,
+        unit = escape(unit),
+        frames = renderer.svg,
+    )?;
+    writer.flush()
+}
+
+/// Render the in-use bytes flamegraph into the SVG file at `path`.
+pub fn dump_flamegraph(path: impl AsRef<Path>) -> io::Result<()> {
+    let _alloc_entry = AllocEntry::new();
+    let file = File::create(path)?;
+    write_flamegraph(BufWriter::new(file), FlamegraphMetric::InuseBytes)
+}
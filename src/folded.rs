@@ -0,0 +1,65 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::{
+    config::ProfAllocConfig,
+    profiler::{AllocSymbolFrames, SymbolFramesWriter},
+};
+
+/// Writes the folded stacks of Brendan Gregg's flamegraph tools, one `root;...;leaf <bytes>` line per stack.
+///
+/// The output can be rendered by `inferno-flamegraph` or `flamegraph.pl`.
+pub(crate) struct FoldedWriter<T: Write> {
+    stacks: HashMap<String, u64>,
+    sample_rate: usize,
+    writer: T,
+}
+
+impl<T: Write> FoldedWriter<T> {
+    pub(crate) fn new(writer: T, config: &ProfAllocConfig) -> Self {
+        Self {
+            stacks: HashMap::new(),
+            sample_rate: config.sample_rate,
+            writer,
+        }
+    }
+}
+
+/// the frame name, `;` separates the frames of the format so it can't be part of a name.
+fn frame_name(name: &str, addr: usize) -> String {
+    if name.is_empty() {
+        format!("[unknown {addr:#x}]")
+    } else {
+        name.replace(';', ",")
+    }
+}
+
+impl<T: Write> SymbolFramesWriter for FoldedWriter<T> {
+    fn write_symbol_frame(&mut self, symbol_frame: AllocSymbolFrames<'_>) -> io::Result<()> {
+        let stack = symbol_frame
+            .frames
+            .iter()
+            .rev()
+            .map(|frame| frame_name(&frame.name, frame.addr as usize))
+            .collect::<Vec<_>>()
+            .join(";");
+        // scale back the sampled allocations.
+        *self.stacks.entry(stack).or_default() +=
+            (symbol_frame.size as u64).saturating_mul(self.sample_rate as u64);
+        Ok(())
+    }
+
+    fn flush(self) -> io::Result<()> {
+        let Self {
+            stacks, mut writer, ..
+        } = self;
+        let mut stacks: Vec<_> = stacks.into_iter().collect();
+        stacks.sort();
+        for (stack, bytes) in stacks {
+            writeln!(writer, "{stack} {bytes}")?;
+        }
+        writer.flush()
+    }
+}
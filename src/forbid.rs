@@ -0,0 +1,128 @@
+use std::{
+    fmt::{self, Write as _},
+    io::{self, Write},
+    marker::PhantomData,
+    thread,
+};
+
+use crate::{
+    entry::{AllocEntry, ForbidScope},
+    frame::Frame,
+    profiler::get_profiler,
+};
+
+/// What a [`ForbidAlloc`] guard does with the allocations it caught when it drops.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ForbidAction {
+    /// panic with the report, unless the thread is already panicking.
+    #[default]
+    Panic,
+    /// write the report to stderr and go on.
+    Report,
+}
+
+/// An allocation made under a [`ForbidAlloc`] guard.
+#[derive(Clone, Debug)]
+pub struct ForbiddenAlloc {
+    pub size: usize,
+    /// the stack of the allocation, innermost first.
+    pub frames: Vec<Frame>,
+}
+
+impl fmt::Display for ForbiddenAlloc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "allocation of {} bytes", self.size)?;
+        for frame in &self.frames {
+            writeln!(f, "    at {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Forbids the allocations of the current thread while alive, see [`forbid_alloc`].
+///
+/// The guard only records inside the allocator, the panic or the report happen when it
+/// drops, so the allocator never unwinds.
+#[must_use = "the allocations are only forbidden while the guard is alive"]
+pub struct ForbidAlloc {
+    scope: Option<ForbidScope>,
+    action: ForbidAction,
+    // the scope belongs to the thread that opened it.
+    _not_send: PhantomData<*const ()>,
+}
+
+impl ForbidAlloc {
+    /// end the scope and hand over the allocations it caught instead of acting on them.
+    pub fn finish(mut self) -> Vec<ForbiddenAlloc> {
+        self.take()
+    }
+
+    fn take(&mut self) -> Vec<ForbiddenAlloc> {
+        let _alloc_entry = AllocEntry::new();
+        let Some(scope) = self.scope.take() else {
+            return Vec::new();
+        };
+        let records = scope.take();
+        drop(scope);
+        let profiler = get_profiler();
+        records
+            .into_iter()
+            .map(|record| ForbiddenAlloc {
+                size: record.size,
+                frames: profiler
+                    .resolve_frames(&record.frames)
+                    .iter()
+                    .map(Frame::from)
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+impl Drop for ForbidAlloc {
+    fn drop(&mut self) {
+        let allocs = self.take();
+        if allocs.is_empty() {
+            return;
+        }
+        let _alloc_entry = AllocEntry::new();
+        let mut report = format!("{} forbidden allocation(s):\n", allocs.len());
+        for alloc in &allocs {
+            let _ = write!(report, "{alloc}");
+        }
+        match self.action {
+            // a panic while unwinding would abort, the report still goes out.
+            ForbidAction::Panic if !thread::panicking() => panic!("{report}"),
+            _ => {
+                let _ = io::stderr().lock().write_all(report.as_bytes());
+            }
+        }
+    }
+}
+
+/// Forbid the allocations of the current thread until the guard drops, then panic with
+/// their stacks if there were any, for the hot paths that must not allocate.
+///
+/// ```should_panic
+/// use prof_mem::{ProfAlloc, ProfAllocConfig, forbid_alloc};
+///
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+///
+/// let _guard = forbid_alloc();
+/// let v = vec![1u8; 64];
+/// # drop(v);
+/// ```
+pub fn forbid_alloc() -> ForbidAlloc {
+    forbid_alloc_with(ForbidAction::Panic)
+}
+
+/// Forbid the allocations of the current thread, with the `action` taken when the guard drops.
+pub fn forbid_alloc_with(action: ForbidAction) -> ForbidAlloc {
+    let _alloc_entry = AllocEntry::new();
+    ForbidAlloc {
+        scope: Some(ForbidScope::new()),
+        action,
+        _not_send: PhantomData,
+    }
+}
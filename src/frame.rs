@@ -0,0 +1,64 @@
+use std::fmt;
+
+use crate::profiler::Symbol;
+
+/// A resolved stack frame, the inlined functions get their own frame.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Frame {
+    pub function: String,
+    pub file: String,
+    pub line: u32,
+    /// the instruction address of the frame.
+    pub address: usize,
+}
+
+impl Frame {
+    /// the function name without the crate disambiguators and the symbol hash.
+    pub fn short_function(&self) -> String {
+        short_name(&self.function)
+    }
+}
+
+impl From<&Symbol> for Frame {
+    fn from(symbol: &Symbol) -> Self {
+        Self {
+            function: symbol.name.clone(),
+            file: symbol.file_name.clone(),
+            line: symbol.line_no,
+            address: symbol.addr as usize,
+        }
+    }
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "{} ({:#x})", self.function, self.address)
+        } else {
+            write!(f, "{} ({}:{})", self.function, self.file, self.line)
+        }
+    }
+}
+
+/// the readable name, without the crate disambiguators and the symbol hash.
+pub(crate) fn short_name(name: &str) -> String {
+    let mut short = String::with_capacity(name.len());
+    let mut in_disambiguator = false;
+    for c in name.chars() {
+        match c {
+            '[' if !in_disambiguator
+                && short.ends_with(|c: char| c.is_alphanumeric() || c == '_') =>
+            {
+                in_disambiguator = true
+            }
+            ']' if in_disambiguator => in_disambiguator = false,
+            _ if in_disambiguator => {}
+            _ => short.push(c),
+        }
+    }
+    match short.rfind("::h") {
+        Some(idx) if short.len() - idx == 19 => short.truncate(idx),
+        _ => {}
+    }
+    short
+}
@@ -0,0 +1,73 @@
+use std::io;
+
+use miniz_oxide::inflate::decompress_to_vec;
+
+use crate::invalid_data;
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+const DEFLATE: u8 = 8;
+
+const FHCRC: u8 = 1 << 1;
+const FEXTRA: u8 = 1 << 2;
+const FNAME: u8 = 1 << 3;
+const FCOMMENT: u8 = 1 << 4;
+
+/// whether the data starts with the gzip magic, pprof files usually do.
+pub(crate) fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// the content of a gzip member, see RFC 1952.
+pub(crate) fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    if data.len() < 18 || !is_gzip(data) {
+        return Err(invalid_data("gzip: truncated header"));
+    }
+    if data[2] != DEFLATE {
+        return Err(invalid_data("gzip: unknown compression method"));
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = data
+            .get(pos..pos + 2)
+            .ok_or_else(|| invalid_data("gzip: truncated extra field"))?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|b| *b == 0))
+                .ok_or_else(|| invalid_data("gzip: truncated header"))?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+    if pos + 8 > data.len() {
+        return Err(invalid_data("gzip: truncated header"));
+    }
+    let content =
+        decompress_to_vec(&data[pos..]).map_err(|_| invalid_data("gzip: corrupt deflate data"))?;
+
+    let trailer = &data[data.len() - 8..];
+    let crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if size != content.len() as u32 || crc != crc32(&content) {
+        return Err(invalid_data("gzip: checksum mismatch"));
+    }
+    Ok(content)
+}
+
+/// the CRC-32 of the gzip trailer, the profiles are read once so a table is not worth it.
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
@@ -0,0 +1,123 @@
+use std::fmt;
+
+/// one class per bit length of the size, the class `k` holds the sizes of `2^(k-1)..2^k`.
+const SIZE_CLASSES: usize = usize::BITS as usize + 1;
+
+#[derive(Clone, Copy, Default)]
+struct SizeClassCounters {
+    live_blocks: u64,
+    live_bytes: u64,
+    total_blocks: u64,
+    total_bytes: u64,
+}
+
+/// The per size class counters, updated under the profiler lock.
+pub(crate) struct SizeHistogramCounters {
+    classes: [SizeClassCounters; SIZE_CLASSES],
+}
+
+impl SizeHistogramCounters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            classes: [SizeClassCounters {
+                live_blocks: 0,
+                live_bytes: 0,
+                total_blocks: 0,
+                total_bytes: 0,
+            }; SIZE_CLASSES],
+        }
+    }
+
+    #[inline(always)]
+    fn class(size: usize) -> usize {
+        (usize::BITS - size.leading_zeros()) as usize
+    }
+
+    #[inline(always)]
+    pub(crate) fn add(&mut self, size: usize) {
+        let class = &mut self.classes[Self::class(size)];
+        class.live_blocks += 1;
+        class.live_bytes += size as u64;
+        class.total_blocks += 1;
+        class.total_bytes += size as u64;
+    }
+
+    #[inline(always)]
+    pub(crate) fn remove(&mut self, size: usize) {
+        let class = &mut self.classes[Self::class(size)];
+        class.live_blocks -= 1;
+        class.live_bytes -= size as u64;
+    }
+
+    pub(crate) fn snapshot(&self) -> SizeHistogram {
+        let classes = self
+            .classes
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.total_blocks > 0)
+            .map(|(k, c)| SizeClass {
+                min_size: if k == 0 { 0 } else { 1 << (k - 1) },
+                max_size: if k == 0 {
+                    0
+                } else {
+                    usize::MAX >> (usize::BITS as usize - k)
+                },
+                live_blocks: c.live_blocks,
+                live_bytes: c.live_bytes,
+                total_blocks: c.total_blocks,
+                total_bytes: c.total_bytes,
+            })
+            .collect();
+        SizeHistogram { classes }
+    }
+}
+
+/// The counters of the tracked allocations in `min_size..=max_size` bytes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SizeClass {
+    pub min_size: usize,
+    pub max_size: usize,
+    /// the blocks not freed yet.
+    pub live_blocks: u64,
+    pub live_bytes: u64,
+    /// all the blocks allocated since the start.
+    pub total_blocks: u64,
+    pub total_bytes: u64,
+}
+
+/// The power-of-two size classes of the tracked allocations, empty classes are left out.
+#[derive(Clone, Debug, Default)]
+pub struct SizeHistogram {
+    pub classes: Vec<SizeClass>,
+}
+
+impl SizeHistogram {
+    /// the class holding the allocations of `size` bytes.
+    pub fn class_of(&self, size: usize) -> Option<&SizeClass> {
+        self.classes
+            .iter()
+            .find(|c| c.min_size <= size && size <= c.max_size)
+    }
+}
+
+impl fmt::Display for SizeHistogram {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{:>24} {:>12} {:>14} {:>12} {:>14}",
+            "size", "live blocks", "live bytes", "total blocks", "total bytes"
+        )?;
+        for c in &self.classes {
+            writeln!(
+                f,
+                "{:>24} {:>12} {:>14} {:>12} {:>14}",
+                format!("{}..={}", c.min_size, c.max_size),
+                c.live_blocks,
+                c.live_bytes,
+                c.total_blocks,
+                c.total_bytes
+            )?;
+        }
+        Ok(())
+    }
+}
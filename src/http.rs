@@ -0,0 +1,303 @@
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+    },
+    thread,
+    time::Duration,
+};
+
+use protobuf::Message;
+
+use crate::{
+    dump::dump_to,
+    entry::AllocEntry,
+    metrics::metrics,
+    profile_proto::{Profile, ProfileMerger, ValueType, parse_profile},
+    report::{SortBy, report_top},
+};
+
+/// the longest window of the delta profiles, `?seconds=`.
+const MAX_DELTA_SECONDS: u64 = 3600;
+const MAX_REQUEST_LINE: u64 = 8 * 1024;
+const MAX_HEADERS: u64 = 8 * 1024;
+/// the connections served at once, a delta profile holds one for its seconds.
+const WORKERS: usize = 4;
+/// the delta profiles served at once, the other requests keep a worker.
+const MAX_DELTAS: usize = WORKERS - 1;
+
+static DELTAS: AtomicUsize = AtomicUsize::new(0);
+
+/// A local HTTP server serving the heap profile the way Go services do, so
+/// `pprof http://localhost:6060/debug/pprof/heap` works against the process.
+///
+/// - `/debug/pprof/heap` the pprof protobuf of the live blocks.
+/// - `?seconds=N` the in-use bytes per stack gained or lost over the next `N` seconds.
+/// - `?sample_index=inuse_objects` the live blocks instead of the bytes, `inuse_space` by default.
+/// - `?debug=1` the text report of the heaviest allocation sites.
+/// - `?gc=1` is accepted and ignored, there is no garbage collector to run.
+/// - `/metrics` the heap statistics for Prometheus, see [`metrics`](crate::metrics()).
+///
+/// Dropping the server leaves it running, [`stop`](HttpServer::stop) ends it.
+#[derive(Debug)]
+pub struct HttpServer {
+    addr: SocketAddr,
+    stopped: Arc<AtomicBool>,
+}
+
+impl HttpServer {
+    /// the address the server listens on, with the port picked by the system for port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stop accepting connections and close the socket, the requests in flight are
+    /// served to the end.
+    pub fn stop(self) {
+        let _alloc_entry = AllocEntry::new();
+        self.stopped.store(true, Ordering::Relaxed);
+        // wake each worker blocked in accept, it sees the flag and leaves.
+        for _ in 0..WORKERS {
+            let _ = TcpStream::connect(self.addr);
+        }
+    }
+}
+
+/// Serve the profile on `addr` from a few background threads until [`HttpServer::stop`],
+/// the memory of the server itself is not tracked.
+///
+/// ```no_run
+/// let server = prof_mem::serve_http("127.0.0.1:6060")?;
+/// eprintln!("pprof http://{}/debug/pprof/heap", server.local_addr());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn serve_http(addr: impl ToSocketAddrs) -> io::Result<HttpServer> {
+    let _alloc_entry = AllocEntry::new();
+    let listener = TcpListener::bind(addr)?;
+    let addr = listener.local_addr()?;
+    let stopped = Arc::new(AtomicBool::new(false));
+    // each worker accepts its own connections, the others go on during a delta profile.
+    for _ in 0..WORKERS {
+        let listener = listener.try_clone()?;
+        let stopped = stopped.clone();
+        thread::Builder::new()
+            .name("prof-mem-http".into())
+            .spawn(move || {
+                let _alloc_entry = AllocEntry::new();
+                for stream in listener.incoming() {
+                    if stopped.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Ok(stream) = stream {
+                        let _ = handle(stream);
+                    }
+                }
+            })?;
+    }
+    Ok(HttpServer { addr, stopped })
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(content_type: &'static str, body: Vec<u8>) -> Self {
+        Self {
+            status: "200 OK",
+            content_type,
+            body,
+        }
+    }
+
+    fn error(status: &'static str, msg: impl Into<String>) -> Self {
+        let mut body = msg.into().into_bytes();
+        body.push(b'\n');
+        Self {
+            status,
+            content_type: "text/plain; charset=utf-8",
+            body,
+        }
+    }
+}
+
+fn handle(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(10)))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    (&mut reader)
+        .take(MAX_REQUEST_LINE)
+        .read_line(&mut request_line)?;
+    // the headers tell nothing the routes need, the first `MAX_HEADERS` bytes are skipped.
+    let mut headers = (&mut reader).take(MAX_HEADERS);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        let read = headers.read_line(&mut line)?;
+        if read == 0 || line == "\r\n" || line == "\n" {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let response = match (parts.next(), parts.next()) {
+        _ if !request_line.ends_with('\n') && request_line.len() as u64 == MAX_REQUEST_LINE => {
+            Response::error("414 URI Too Long", "the request line is too long")
+        }
+        (Some("GET"), Some(target)) => {
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            route(path, query)
+        }
+        (Some(_), Some(_)) => Response::error("405 Method Not Allowed", "only GET is served"),
+        _ => Response::error("400 Bad Request", "malformed request"),
+    };
+    let mut stream = &stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        response.content_type,
+        response.body.len()
+    )?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// the value of the query parameter `name`, the values of pprof need no decoding.
+fn param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .map(|pair| pair.split_once('=').unwrap_or((pair, "")))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+fn route(path: &str, query: &str) -> Response {
+    match path {
+        "/debug/pprof/heap" => heap(query),
+        "/metrics" => Response::ok(
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics().into_bytes(),
+        ),
+        "/debug/pprof" | "/debug/pprof/" => {
+            Response::ok("text/plain; charset=utf-8", b"/debug/pprof/heap\n".to_vec())
+        }
+        _ => Response::error("404 Not Found", format!("{path} not found")),
+    }
+}
+
+fn heap(query: &str) -> Response {
+    if param(query, "debug").is_some_and(|debug| debug != "0") {
+        let report = report_top(100, SortBy::InuseBytes);
+        return Response::ok("text/plain; charset=utf-8", report.into_bytes());
+    }
+    let objects = match param(query, "sample_index") {
+        None | Some("") | Some("inuse_space") | Some("space") => false,
+        Some("inuse_objects") | Some("objects") => true,
+        Some(index) => {
+            return Response::error("400 Bad Request", format!("unknown sample_index {index}"));
+        }
+    };
+    let seconds = match param(query, "seconds").map(str::parse::<u64>) {
+        None => None,
+        Some(Ok(seconds)) if seconds <= MAX_DELTA_SECONDS => Some(seconds),
+        Some(_) => {
+            return Response::error(
+                "400 Bad Request",
+                format!("seconds is a count up to {MAX_DELTA_SECONDS}"),
+            );
+        }
+    };
+
+    let profile = match seconds {
+        None => snapshot(objects),
+        Some(seconds) => {
+            let Some(_slot) = DeltaSlot::take() else {
+                return Response::error(
+                    "503 Service Unavailable",
+                    format!("{MAX_DELTAS} delta profiles are already running"),
+                );
+            };
+            snapshot(objects).and_then(|base| {
+                thread::sleep(Duration::from_secs(seconds));
+                delta(&base, &snapshot(objects)?)
+            })
+        }
+    };
+    match profile.and_then(|profile| Ok(profile.write_to_bytes()?)) {
+        Ok(body) => Response::ok("application/octet-stream", body),
+        Err(err) => Response::error("500 Internal Server Error", err.to_string()),
+    }
+}
+
+/// one of the `MAX_DELTAS` delta profiles running, given back on drop.
+struct DeltaSlot;
+
+impl DeltaSlot {
+    fn take() -> Option<Self> {
+        DELTAS
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |deltas| {
+                (deltas < MAX_DELTAS).then_some(deltas + 1)
+            })
+            .ok()
+            .map(|_| Self)
+    }
+}
+
+impl Drop for DeltaSlot {
+    fn drop(&mut self) {
+        DELTAS.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// the current profile, with the live blocks as values for `objects`.
+fn snapshot(objects: bool) -> io::Result<Profile> {
+    let mut buf = Vec::new();
+    dump_to(&mut buf)?;
+    let mut profile = parse_profile(&buf)?;
+    if objects {
+        let count = profile.string_table.len() as i64;
+        profile.string_table.push("objects".into());
+        profile.string_table.push("count".into());
+        profile.sample_type = vec![ValueType {
+            type_: count,
+            unit: count + 1,
+            ..Default::default()
+        }];
+        // each sample is one block standing for `period` blocks.
+        let blocks = profile.period.max(1);
+        for sample in &mut profile.sample {
+            sample.value = vec![blocks];
+        }
+    }
+    Ok(profile)
+}
+
+/// the values per stack of `new` minus the ones of `base`, the labels of the blocks
+/// are dropped so the samples of a stack add up.
+fn delta(base: &Profile, new: &Profile) -> io::Result<Profile> {
+    let mut base = base.clone();
+    for sample in &mut base.sample {
+        sample.label.clear();
+        sample.value.iter_mut().for_each(|value| *value = -*value);
+    }
+    let mut new = new.clone();
+    new.sample
+        .iter_mut()
+        .for_each(|sample| sample.label.clear());
+
+    let mut merger = ProfileMerger::new();
+    merger.add_profile(&new, None)?;
+    merger.add_profile(&base, None)?;
+    let mut profile = merger.finish();
+    profile
+        .sample
+        .retain(|sample| sample.value.iter().any(|v| *v != 0));
+    profile.time_nanos = new.time_nanos;
+    profile.duration_nanos = new.time_nanos - base.time_nanos;
+    Ok(profile)
+}
@@ -0,0 +1,77 @@
+//! The JSON lines export of the allocation sites, the schema is documented on `DumpFormat::Json`.
+
+use std::{
+    io::{self, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{json::JsonStr, process, profiler::get_profiler};
+
+/// the version of the schema above.
+pub(crate) const JSON_SCHEMA: u32 = 1;
+
+pub(crate) fn write_json<W: Write>(mut writer: W) -> io::Result<()> {
+    let profiler = get_profiler();
+    let _guard = profiler.lock();
+    let config = profiler.config();
+    let scale = config.sample_rate as u64;
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let comments: Vec<_> = process::comments(&config)
+        .iter()
+        .map(|comment| JsonStr(comment).to_string())
+        .collect();
+    writeln!(
+        writer,
+        "{{\"type\":\"profile\",\"schema\":{JSON_SCHEMA},\"version\":{},\"time_nanos\":{},\"duration_nanos\":{},\"pid\":{},\"sample_rate\":{},\"comments\":[{}]}}",
+        JsonStr(env!("CARGO_PKG_VERSION")),
+        time.as_nanos(),
+        profiler.uptime().as_nanos(),
+        std::process::id(),
+        config.sample_rate,
+        comments.join(","),
+    )?;
+
+    let oldest = profiler.oldest_ages();
+    for site in profiler.resolved_sites() {
+        let stats = &site.stats;
+        if stats.total_blocks == 0 {
+            continue;
+        }
+        let labels = match oldest.get(&site.id) {
+            Some(age) if stats.live_blocks > 0 => format!(
+                "{{\"age_ms\":{},\"bytes\":{}}}",
+                age / 1_000_000,
+                stats.live_bytes / stats.live_blocks
+            ),
+            _ => "{}".to_string(),
+        };
+        let frames: Vec<_> = site
+            .frames
+            .iter()
+            .map(|frame| {
+                format!(
+                    "{{\"function\":{},\"file\":{},\"line\":{},\"address\":\"{:#x}\"}}",
+                    JsonStr(&frame.name),
+                    JsonStr(&frame.file_name),
+                    frame.line_no,
+                    frame.addr as usize
+                )
+            })
+            .collect();
+        writeln!(
+            writer,
+            "{{\"type\":\"stack\",\"id\":{},\"inuse_bytes\":{},\"inuse_blocks\":{},\"alloc_bytes\":{},\"alloc_blocks\":{},\"labels\":{},\"frames\":[{}]}}",
+            site.id,
+            stats.live_bytes.saturating_mul(scale),
+            stats.live_blocks.saturating_mul(scale),
+            stats.total_bytes.saturating_mul(scale),
+            stats.total_blocks.saturating_mul(scale),
+            labels,
+            frames.join(","),
+        )?;
+    }
+    writer.flush()
+}
@@ -2,37 +2,257 @@
 #[macro_use]
 mod msg;
 
+mod budget;
+mod config;
+mod dhat;
+mod dump;
 mod entry;
+mod fault;
+#[cfg(feature = "flamegraph")]
+mod flamegraph;
+mod folded;
+mod forbid;
+mod frame;
+mod gzip;
+mod histogram;
+#[cfg(feature = "http")]
+mod http;
+mod json;
+mod json_writer;
+mod lifetime;
+mod measure;
+mod metrics;
+mod pprof;
+mod process;
 mod profile_proto;
 mod profiler;
-pub use crate::profiler::dump;
-use crate::profiler::get_profiler;
-use std::alloc::{GlobalAlloc, System};
+mod report;
+mod stacks;
+mod stats;
+mod trace;
+pub use crate::budget::{
+    Budget, BudgetUsage, BudgetViolation, budget_usage, budget_violations, set_budget_callback,
+    set_budgets,
+};
+pub use crate::config::ProfAllocConfig;
+pub use crate::dump::{DumpFormat, DumpOptions, dump, dump_to, dump_to_with, dump_with};
+pub use crate::fault::{FaultRule, InjectedFault, clear_faults, inject_faults, injected_faults};
+#[cfg(feature = "flamegraph")]
+pub use crate::flamegraph::{
+    FlamegraphMetric, dump_flamegraph, write_flamegraph, write_profile_flamegraph,
+};
+pub use crate::forbid::{
+    ForbidAction, ForbidAlloc, ForbiddenAlloc, forbid_alloc, forbid_alloc_with,
+};
+pub use crate::frame::Frame;
+pub use crate::histogram::{SizeClass, SizeHistogram};
+#[cfg(feature = "http")]
+pub use crate::http::{HttpServer, serve_http};
+pub use crate::lifetime::{
+    LIFETIME_BUCKET_BOUNDS, LIFETIME_BUCKETS, LifetimeHistogram, LifetimeReport, SiteLifetime,
+};
+pub use crate::measure::{Measurement, measure};
+pub use crate::metrics::{HeapMetrics, heap_metrics, metrics};
+pub use crate::pprof::{HeapProfile, LabelValue, ProfileSample, SampleLabel, SampleType};
+pub use crate::profile_proto::{ProfileMerger, SOURCE_LABEL, merge_profiles};
+pub use crate::profiler::{
+    DEFAULT_SKIP_PREFIXES, is_enabled, lifetimes, set_enabled, size_histogram,
+};
+use crate::profiler::{HeapProfiler, LockGuard, get_profiler};
+pub use crate::report::{SortBy, TopSite, print_top, report_top, top_sites};
+use crate::stats::STATS;
+pub use crate::stats::{HeapStats, stats};
+use crate::trace::TraceRealloc;
+pub use crate::trace::{Trace, TraceBlock, TraceEvent, TraceRecorder, record_trace};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::{io, ptr};
 
-use crate::entry::AllocEntry;
+use crate::entry::{AllocEntry, ForbidScope, ForbiddenRecord, ThreadCounters};
+use crate::fault::FAULTS;
 
-pub struct ProfAlloc(pub usize);
+/// the error of a malformed file or profile read back.
+pub(crate) fn invalid_data(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// The profiling allocator, the memory comes from the inner allocator `A`.
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc<Jemalloc> = ProfAlloc::with_allocator(Jemalloc, ProfAllocConfig::new());
+/// ```
+pub struct ProfAlloc<A: GlobalAlloc = System> {
+    inner: A,
+    config: ProfAllocConfig,
+}
+
+impl ProfAlloc<System> {
+    /// Profile the allocations of the system allocator.
+    pub const fn new(config: ProfAllocConfig) -> Self {
+        Self::with_allocator(System, config)
+    }
+}
+
+impl<A: GlobalAlloc> ProfAlloc<A> {
+    /// Profile the allocations of `inner`.
+    pub const fn with_allocator(inner: A, config: ProfAllocConfig) -> Self {
+        Self { inner, config }
+    }
+
+    /// the config of the allocator.
+    pub const fn config(&self) -> &ProfAllocConfig {
+        &self.config
+    }
+
+    /// the inner allocator.
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
 
-unsafe impl GlobalAlloc for ProfAlloc {
-    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
-        let ptr = unsafe { System.alloc(layout) };
+    /// the profiler, initialized with the config of the allocator at the first call.
+    #[inline(always)]
+    fn profiler(&self) -> &'static HeapProfiler {
+        let profiler = get_profiler();
+        profiler.init_once(&self.config);
+        profiler
+    }
+
+    /// track the block, the budget violations are returned for `budget::notify`.
+    #[inline(always)]
+    fn track(&self, ptr: *mut u8, layout: Layout) -> Vec<BudgetViolation> {
+        // filter by size before capturing the stack.
+        if ptr.is_null() || !self.config.tracks_size(layout.size()) {
+            return Vec::new();
+        }
         let alloc_entry = AllocEntry::new();
         // if in the alloc to alloc the memory, we don't need analyze.
         if !alloc_entry.top_entry() {
-            return ptr;
+            return Vec::new();
         }
+        let profiler = self.profiler();
+        if profiler.is_enabled() && profiler.sampled() {
+            profiler.insert(ptr, layout)
+        } else {
+            Vec::new()
+        }
+    }
 
-        let profiler = get_profiler(Some(self.0));
-        profiler.insert(ptr, layout);
-        ptr
+    /// whether the allocation of `size` bytes fails by a rule of [`inject_faults`].
+    #[inline(always)]
+    fn inject_fault(&self, size: usize) -> bool {
+        if !FAULTS.may_fail(size) {
+            return false;
+        }
+        let alloc_entry = AllocEntry::new();
+        // the allocations of the profiler itself never fail.
+        alloc_entry.top_entry() && FAULTS.check(self.profiler(), size)
+    }
+
+    /// record the allocation for a `forbid_alloc` guard of the thread, if any.
+    #[inline(always)]
+    fn check_forbidden(&self, size: usize) {
+        if !ForbidScope::forbids() {
+            return;
+        }
+        let _alloc_entry = AllocEntry::new();
+        let frames = self.profiler().trace_frames();
+        ForbidScope::record(ForbiddenRecord { size, frames });
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
-        unsafe { System.dealloc(ptr, layout) };
+    /// the profiler lock for the reallocation of a tracked block, `None` for an untracked one.
+    #[inline(always)]
+    fn realloc_lock(&self, ptr: *mut u8, layout: Layout) -> Option<LockGuard<'static>> {
+        if !self.config.tracks_size(layout.size()) || !AllocEntry::new().top_entry() {
+            return None;
+        }
+        let profiler = self.profiler();
+        if !profiler.has_blocks() {
+            return None;
+        }
+        let guard = profiler.lock();
+        profiler.is_tracked(ptr).then_some(guard)
+    }
+
+    #[inline(always)]
+    fn untrack(&self, ptr: *mut u8, layout: Layout) {
+        // the blocks out of the size filter were never tracked.
+        if !self.config.tracks_size(layout.size()) {
+            return;
+        }
         let alloc_entry = AllocEntry::new();
         if alloc_entry.top_entry() {
-            let profiler = get_profiler(Some(self.0));
-            profiler.remove(ptr);
+            self.profiler().remove(ptr);
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for ProfAlloc<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if self.inject_fault(layout.size()) {
+            return ptr::null_mut();
+        }
+        let ptr = unsafe { self.inner.alloc(layout) };
+        if !ptr.is_null() {
+            STATS.alloc(layout.size());
+            ThreadCounters::alloc(layout.size());
+            self.check_forbidden(layout.size());
+        }
+        let violations = self.track(ptr, layout);
+        budget::notify(get_profiler(), &violations);
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if self.inject_fault(layout.size()) {
+            return ptr::null_mut();
         }
+        let ptr = unsafe { self.inner.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            STATS.alloc(layout.size());
+            ThreadCounters::alloc(layout.size());
+            self.check_forbidden(layout.size());
+        }
+        let violations = self.track(ptr, layout);
+        budget::notify(get_profiler(), &violations);
+        ptr
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if self.inject_fault(new_size) {
+            return ptr::null_mut();
+        }
+        // hold the lock over the inner realloc of a tracked block, once the block moved the
+        // old address can be handed out to another thread, which must not track it before
+        // it is untracked. an untracked block is not untracked at all.
+        let guard = self.realloc_lock(ptr, layout);
+        let new_ptr = unsafe { self.inner.realloc(ptr, layout, new_size) };
+        // on failure the old block is untouched and stays tracked.
+        if !new_ptr.is_null() {
+            STATS.realloc(layout.size(), new_size);
+            ThreadCounters::realloc(layout.size(), new_size);
+            self.check_forbidden(new_size);
+            let violations = {
+                // the untrack and the track make one event of a trace, which keeps the
+                // lock until the new block is tracked.
+                let trace = TraceRealloc::new(ptr);
+                if guard.is_some() {
+                    self.untrack(ptr, layout);
+                }
+                let _guard = guard.filter(|_| trace.is_some());
+                self.track(new_ptr, unsafe {
+                    Layout::from_size_align_unchecked(new_size, layout.align())
+                })
+            };
+            budget::notify(get_profiler(), &violations);
+        }
+        new_ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // untrack first, once freed the address can be handed out to another thread.
+        self.untrack(ptr, layout);
+        STATS.dealloc(layout.size());
+        ThreadCounters::dealloc(layout.size());
+        unsafe { self.inner.dealloc(ptr, layout) };
     }
 }
@@ -0,0 +1,132 @@
+use std::{cmp::Reverse, fmt, time::Duration};
+
+use crate::frame::Frame;
+
+/// The buckets of the lifetime histograms, one per decade from 1µs.
+pub const LIFETIME_BUCKETS: usize = 10;
+
+/// The exclusive upper bounds of the lifetime buckets, the last bucket is unbounded.
+pub const LIFETIME_BUCKET_BOUNDS: [Duration; LIFETIME_BUCKETS - 1] = [
+    Duration::from_micros(1),
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+    Duration::from_secs(100),
+];
+
+/// The lifetimes of the freed blocks, counted per bucket of [`LIFETIME_BUCKET_BOUNDS`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LifetimeHistogram {
+    pub buckets: [u64; LIFETIME_BUCKETS],
+}
+
+impl LifetimeHistogram {
+    #[inline(always)]
+    pub(crate) fn record(&mut self, lifetime: u64) {
+        let bucket = LIFETIME_BUCKET_BOUNDS
+            .iter()
+            .position(|bound| (lifetime as u128) < bound.as_nanos())
+            .unwrap_or(LIFETIME_BUCKETS - 1);
+        self.buckets[bucket] += 1;
+    }
+}
+
+/// The lifetimes of the blocks of one allocation site.
+#[derive(Clone, Debug)]
+pub struct SiteLifetime {
+    pub frames: Vec<Frame>,
+    pub total_blocks: u64,
+    pub total_bytes: u64,
+    pub freed_blocks: u64,
+    pub live_blocks: u64,
+    pub live_bytes: u64,
+    /// the mean lifetime of the freed blocks.
+    pub mean_lifetime: Duration,
+    /// the longest lifetime of the freed blocks.
+    pub max_lifetime: Duration,
+    /// the age of the oldest live block.
+    pub oldest_live: Duration,
+    pub histogram: LifetimeHistogram,
+}
+
+impl SiteLifetime {
+    /// the longest of the freed lifetimes and the live ages.
+    pub fn longest(&self) -> Duration {
+        self.max_lifetime.max(self.oldest_live)
+    }
+}
+
+/// The lifetimes per allocation site, like the lifetime data of DHAT.
+#[derive(Clone, Debug, Default)]
+pub struct LifetimeReport {
+    pub sites: Vec<SiteLifetime>,
+}
+
+impl LifetimeReport {
+    /// the threshold of the short-lived and long-lived tables of the `Display` output.
+    pub const SHORT_LIVED: Duration = Duration::from_millis(1);
+    pub const LONG_LIVED: Duration = Duration::from_secs(1);
+
+    /// the churn sites, whose freed blocks live shorter than `threshold` on average,
+    /// most freed blocks first.
+    pub fn short_lived(&self, threshold: Duration) -> Vec<&SiteLifetime> {
+        let mut sites: Vec<_> = self
+            .sites
+            .iter()
+            .filter(|s| s.freed_blocks > 0 && s.mean_lifetime < threshold)
+            .collect();
+        sites.sort_by_key(|s| Reverse(s.freed_blocks));
+        sites
+    }
+
+    /// the sites having blocks living for `threshold` or longer, longest first.
+    pub fn long_lived(&self, threshold: Duration) -> Vec<&SiteLifetime> {
+        let mut sites: Vec<_> = self
+            .sites
+            .iter()
+            .filter(|s| s.longest() >= threshold)
+            .collect();
+        sites.sort_by_key(|s| Reverse(s.longest()));
+        sites
+    }
+}
+
+fn write_sites(f: &mut fmt::Formatter<'_>, sites: &[&SiteLifetime]) -> fmt::Result {
+    writeln!(
+        f,
+        "{:>12} {:>12} {:>14} {:>14} {:>14}  site",
+        "freed", "live", "mean", "max", "oldest live"
+    )?;
+    for site in sites.iter().take(10) {
+        let top = site
+            .frames
+            .first()
+            .map(|frame| frame.to_string())
+            .unwrap_or_default();
+        writeln!(
+            f,
+            "{:>12} {:>12} {:>14} {:>14} {:>14}  {}",
+            site.freed_blocks,
+            site.live_blocks,
+            format!("{:.1?}", site.mean_lifetime),
+            format!("{:.1?}", site.max_lifetime),
+            format!("{:.1?}", site.oldest_live),
+            top
+        )?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for LifetimeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "short-lived sites (< {:?}):", Self::SHORT_LIVED)?;
+        write_sites(f, &self.short_lived(Self::SHORT_LIVED))?;
+        writeln!(f)?;
+        writeln!(f, "long-lived sites (>= {:?}):", Self::LONG_LIVED)?;
+        write_sites(f, &self.long_lived(Self::LONG_LIVED))
+    }
+}
@@ -0,0 +1,51 @@
+use crate::entry::{MeasureScope, ThreadCounters};
+
+/// The allocator calls of the current thread during [`measure`], counted whatever the
+/// config of the profiler.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Measurement {
+    /// the calls of `alloc` and `alloc_zeroed`.
+    pub allocations: u64,
+    pub deallocations: u64,
+    pub reallocations: u64,
+    /// the bytes allocated, the new sizes of the reallocations included.
+    pub allocated_bytes: u64,
+    /// the bytes freed, the old sizes of the reallocations included.
+    pub freed_bytes: u64,
+}
+
+impl Measurement {
+    /// the bytes allocated and not freed, negative when the closure freed older blocks.
+    pub fn retained_bytes(&self) -> i64 {
+        self.allocated_bytes as i64 - self.freed_bytes as i64
+    }
+}
+
+/// Run `f` and count the allocator calls of the current thread meanwhile, the calls of
+/// the other threads, even the ones `f` spawns, are not counted.
+///
+/// ```
+/// use prof_mem::{ProfAlloc, ProfAllocConfig, measure};
+///
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().enabled(false));
+///
+/// let (sum, m) = measure(|| (0..100u64).sum::<u64>());
+/// assert_eq!(sum, 4950);
+/// assert_eq!(m.allocations + m.reallocations, 0, "the hot path must not allocate");
+/// ```
+pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Measurement) {
+    let scope = MeasureScope::new();
+    let start = ThreadCounters::current();
+    let value = f();
+    let end = ThreadCounters::current();
+    drop(scope);
+    let measurement = Measurement {
+        allocations: end.allocs - start.allocs,
+        deallocations: end.deallocs - start.deallocs,
+        reallocations: end.reallocs - start.reallocs,
+        allocated_bytes: end.allocated_bytes - start.allocated_bytes,
+        freed_bytes: end.freed_bytes - start.freed_bytes,
+    };
+    (value, measurement)
+}
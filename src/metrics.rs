@@ -0,0 +1,116 @@
+use std::{fmt, time::Duration};
+
+use crate::{entry::AllocEntry, profiler::get_profiler};
+
+/// The event counters of the metrics, kept by `HeapProfiler::insert` and `remove`.
+#[derive(Clone, Copy, Debug, Default)]
+pub(crate) struct MetricsCounters {
+    pub(crate) allocs: u64,
+    pub(crate) frees: u64,
+    /// the time spent in the profiler to track and untrack the blocks.
+    pub(crate) overhead_nanos: u64,
+}
+
+impl MetricsCounters {
+    pub(crate) const fn new() -> Self {
+        Self {
+            allocs: 0,
+            frees: 0,
+            overhead_nanos: 0,
+        }
+    }
+}
+
+/// The heap statistics of [`metrics`], the counts of the tracked blocks are scaled back
+/// by the sample rate.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapMetrics {
+    pub inuse_bytes: u64,
+    pub allocations: u64,
+    pub frees: u64,
+    /// the highest in-use bytes since the start.
+    pub peak_bytes: u64,
+    /// the blocks in the table of the profiler, not scaled.
+    pub tracked_blocks: u64,
+    /// the time spent in the profiler, mostly capturing the stacks.
+    pub overhead: Duration,
+}
+
+fn metric(
+    f: &mut fmt::Formatter<'_>,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl fmt::Display,
+) -> fmt::Result {
+    writeln!(f, "# HELP {name} {help}")?;
+    writeln!(f, "# TYPE {name} {kind}")?;
+    writeln!(f, "{name} {value}")
+}
+
+/// The Prometheus text exposition format.
+impl fmt::Display for HeapMetrics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        metric(
+            f,
+            "prof_mem_inuse_bytes",
+            "gauge",
+            "Bytes of the live tracked blocks.",
+            self.inuse_bytes,
+        )?;
+        metric(
+            f,
+            "prof_mem_allocations_total",
+            "counter",
+            "Tracked allocations since the start.",
+            self.allocations,
+        )?;
+        metric(
+            f,
+            "prof_mem_frees_total",
+            "counter",
+            "Frees of tracked blocks since the start.",
+            self.frees,
+        )?;
+        metric(
+            f,
+            "prof_mem_peak_inuse_bytes",
+            "gauge",
+            "Highest in-use bytes since the start.",
+            self.peak_bytes,
+        )?;
+        metric(
+            f,
+            "prof_mem_tracked_blocks",
+            "gauge",
+            "Blocks in the table of the profiler.",
+            self.tracked_blocks,
+        )?;
+        metric(
+            f,
+            "prof_mem_overhead_seconds_total",
+            "counter",
+            "Time spent tracking the allocations and the frees.",
+            self.overhead.as_secs_f64(),
+        )
+    }
+}
+
+/// The heap statistics, see [`metrics`].
+pub fn heap_metrics() -> HeapMetrics {
+    let _alloc_entry = AllocEntry::new();
+    get_profiler().metrics()
+}
+
+/// The heap statistics in the Prometheus text exposition format, for a `/metrics` handler.
+///
+/// ```text
+/// # HELP prof_mem_inuse_bytes Bytes of the live tracked blocks.
+/// # TYPE prof_mem_inuse_bytes gauge
+/// prof_mem_inuse_bytes 1048576
+/// ...
+/// ```
+pub fn metrics() -> String {
+    let _alloc_entry = AllocEntry::new();
+    heap_metrics().to_string()
+}
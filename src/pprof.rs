@@ -0,0 +1,220 @@
+use std::{collections::HashMap, fs, io, path::Path};
+
+use crate::{
+    frame::Frame,
+    invalid_data,
+    profile_proto::{Profile, parse_profile},
+};
+
+/// A sample type of a profile, like `space/bytes`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SampleType {
+    /// the kind of the values, `space` for the dumps of this crate.
+    pub kind: String,
+    pub unit: String,
+}
+
+/// The value of a sample label, pprof labels hold either a string or a number.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LabelValue {
+    Str(String),
+    /// a number with its unit, the unit is empty when the profile does not tell it.
+    Num {
+        value: i64,
+        unit: String,
+    },
+}
+
+/// A label of a sample, like the `alloc` address of a block.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SampleLabel {
+    pub key: String,
+    pub value: LabelValue,
+}
+
+/// A sample with its resolved frames, the leaf frame first.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProfileSample {
+    pub frames: Vec<Frame>,
+    /// one value per sample type of the profile.
+    pub values: Vec<i64>,
+    pub labels: Vec<SampleLabel>,
+}
+
+impl ProfileSample {
+    /// the value of the first label with the `key`.
+    pub fn label(&self, key: &str) -> Option<&LabelValue> {
+        self.labels
+            .iter()
+            .find(|label| label.key == key)
+            .map(|label| &label.value)
+    }
+
+    /// the value of the string label `key`.
+    pub fn str_label(&self, key: &str) -> Option<&str> {
+        match self.label(key)? {
+            LabelValue::Str(value) => Some(value),
+            LabelValue::Num { .. } => None,
+        }
+    }
+
+    /// the value of the numeric label `key`.
+    pub fn num_label(&self, key: &str) -> Option<i64> {
+        match self.label(key)? {
+            LabelValue::Num { value, .. } => Some(*value),
+            LabelValue::Str(_) => None,
+        }
+    }
+}
+
+/// A pprof profile with the string table and the ids resolved, read back from a dump
+/// of this crate or from any other pprof file.
+///
+/// ```no_run
+/// let profile = prof_mem::HeapProfile::open("mem.1700000000000.pb")?;
+/// let inuse = profile.sample_index("space").unwrap_or_default();
+/// println!("{} bytes in use", profile.total(inuse));
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HeapProfile {
+    pub sample_types: Vec<SampleType>,
+    pub period_type: Option<SampleType>,
+    pub period: i64,
+    /// the time of the collection, in nanoseconds since the epoch.
+    pub time_nanos: i64,
+    pub duration_nanos: i64,
+    pub comments: Vec<String>,
+    pub samples: Vec<ProfileSample>,
+}
+
+impl HeapProfile {
+    /// parse a serialized profile, gzip compressed or not.
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        Self::from_proto(&parse_profile(data)?)
+    }
+
+    /// read a profile from `reader` until its end.
+    pub fn read_from<R: io::Read>(mut reader: R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::parse(&data)
+    }
+
+    /// read the profile file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    /// resolve the ids and string indexes of a protobuf profile.
+    pub(crate) fn from_proto(profile: &Profile) -> io::Result<Self> {
+        let string = |idx: i64| -> io::Result<String> {
+            usize::try_from(idx)
+                .ok()
+                .and_then(|idx| profile.string_table.get(idx))
+                .cloned()
+                .ok_or_else(|| invalid_data(format!("string index {idx} out of the table")))
+        };
+        let value_type = |kind: i64, unit: i64| -> io::Result<SampleType> {
+            Ok(SampleType {
+                kind: string(kind)?,
+                unit: string(unit)?,
+            })
+        };
+
+        let mut functions = HashMap::with_capacity(profile.function.len());
+        for func in &profile.function {
+            functions.insert(func.id, func);
+        }
+        let mut locations = HashMap::with_capacity(profile.location.len());
+        for loc in &profile.location {
+            // the lines of a location go from the inlined leaf to its caller.
+            let mut frames = Vec::with_capacity(loc.line.len().max(1));
+            for line in &loc.line {
+                let func = functions.get(&line.function_id).ok_or_else(|| {
+                    invalid_data(format!("unknown function id {}", line.function_id))
+                })?;
+                frames.push(Frame {
+                    function: string(func.name)?,
+                    file: string(func.filename)?,
+                    line: line.line as u32,
+                    address: loc.address as usize,
+                });
+            }
+            // an unsymbolized location still has its address.
+            if frames.is_empty() {
+                frames.push(Frame {
+                    address: loc.address as usize,
+                    ..Default::default()
+                });
+            }
+            locations.insert(loc.id, frames);
+        }
+
+        let mut samples = Vec::with_capacity(profile.sample.len());
+        for sample in &profile.sample {
+            let mut frames = Vec::with_capacity(sample.location_id.len());
+            for id in &sample.location_id {
+                let loc = locations
+                    .get(id)
+                    .ok_or_else(|| invalid_data(format!("unknown location id {id}")))?;
+                frames.extend(loc.iter().cloned());
+            }
+            let mut labels = Vec::with_capacity(sample.label.len());
+            for label in &sample.label {
+                let value = if label.str != 0 {
+                    LabelValue::Str(string(label.str)?)
+                } else {
+                    LabelValue::Num {
+                        value: label.num,
+                        unit: string(label.num_unit)?,
+                    }
+                };
+                labels.push(SampleLabel {
+                    key: string(label.key)?,
+                    value,
+                });
+            }
+            samples.push(ProfileSample {
+                frames,
+                values: sample.value.clone(),
+                labels,
+            });
+        }
+
+        Ok(Self {
+            sample_types: profile
+                .sample_type
+                .iter()
+                .map(|t| value_type(t.type_, t.unit))
+                .collect::<io::Result<_>>()?,
+            period_type: profile
+                .period_type
+                .as_ref()
+                .map(|t| value_type(t.type_, t.unit))
+                .transpose()?,
+            period: profile.period,
+            time_nanos: profile.time_nanos,
+            duration_nanos: profile.duration_nanos,
+            comments: profile
+                .comment
+                .iter()
+                .map(|idx| string(*idx))
+                .collect::<io::Result<_>>()?,
+            samples,
+        })
+    }
+
+    /// the index of the values of the sample type `kind`.
+    pub fn sample_index(&self, kind: &str) -> Option<usize> {
+        self.sample_types.iter().position(|t| t.kind == kind)
+    }
+
+    /// the sum of the values at `index` over all the samples.
+    pub fn total(&self, index: usize) -> i64 {
+        self.samples
+            .iter()
+            .filter_map(|sample| sample.values.get(index))
+            .sum()
+    }
+}
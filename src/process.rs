@@ -0,0 +1,54 @@
+use std::{env, fs};
+
+use crate::config::ProfAllocConfig;
+
+/// the command line of the process, the arguments joined by spaces.
+pub(crate) fn command_line() -> String {
+    env::args_os()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// the path of the executable of the process.
+pub(crate) fn executable() -> Option<String> {
+    env::current_exe()
+        .ok()
+        .map(|path| path.to_string_lossy().into_owned())
+}
+
+/// the host name, from procfs or the environment.
+pub(crate) fn hostname() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .map(|name| name.trim().to_string())
+        .or_else(|| env::var("HOSTNAME").ok())
+        .or_else(|| env::var("COMPUTERNAME").ok())
+        .filter(|name| !name.is_empty())
+}
+
+/// the comments of a dump, the crate version, the process and the config.
+pub(crate) fn comments(config: &ProfAllocConfig) -> Vec<String> {
+    let mut comments = vec![
+        format!("prof-mem {}", env!("CARGO_PKG_VERSION")),
+        format!("pid: {}", std::process::id()),
+    ];
+    if let Some(executable) = executable() {
+        comments.push(format!("executable: {executable}"));
+    }
+    comments.push(format!("command line: {}", command_line()));
+    if let Some(hostname) = hostname() {
+        comments.push(format!("hostname: {hostname}"));
+    }
+    comments.push(format!(
+        "max depth: {}, sample rate: {}",
+        config.max_depth, config.sample_rate
+    ));
+    if config.has_size_filter() {
+        comments.push(format!(
+            "size filter: {}..={} bytes",
+            config.min_size, config.max_size
+        ));
+    }
+    comments
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::{HashMap, HashSet},
+    io::{self, Write},
+};
+
+use protobuf::Message;
+
+use crate::{
+    invalid_data,
+    profile_proto::{
+        parse_profile,
+        profile_proto::{Function, Label, Line, Location, Mapping, Profile, Sample, ValueType},
+    },
+};
+
+/// The key of the label naming the profile a merged sample comes from.
+pub const SOURCE_LABEL: &str = "source";
+
+type MappingKey = (u64, u64, u64, i64, i64);
+type FunctionKey = (i64, i64, i64, i64);
+type LocationKey = (u64, u64, Vec<(u64, i64)>, bool);
+type LabelKey = (i64, i64, i64, i64);
+
+/// Merges pprof profiles into one, the string, mapping, function and location tables
+/// of each profile are re-indexed into the tables of the merged profile, the way
+/// `ProfileProtoWriter` builds them, and the identical samples add up.
+///
+/// ```no_run
+/// use prof_mem::ProfileMerger;
+///
+/// let mut merger = ProfileMerger::new();
+/// for path in ["mem.1.pb", "mem.2.pb"] {
+///     merger.add(&std::fs::read(path)?, Some(path))?;
+/// }
+/// merger.write_to(std::fs::File::create("merged.pb")?)?;
+/// # Ok::<(), std::io::Error>(())
+/// ```
+#[derive(Default)]
+pub struct ProfileMerger {
+    profile: Profile,
+    profiles: usize,
+    end_nanos: i64,
+    strings: HashMap<String, i64>,
+    mappings: HashMap<MappingKey, u64>,
+    functions: HashMap<FunctionKey, u64>,
+    locations: HashMap<LocationKey, u64>,
+    samples: HashMap<(Vec<u64>, Vec<LabelKey>), usize>,
+}
+
+impl ProfileMerger {
+    pub fn new() -> Self {
+        let mut merger = Self::default();
+        merger.string("");
+        merger
+    }
+
+    /// the count of the profiles merged so far.
+    pub fn len(&self) -> usize {
+        self.profiles
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles == 0
+    }
+
+    fn string(&mut self, s: &str) -> i64 {
+        if let Some(idx) = self.strings.get(s) {
+            return *idx;
+        }
+        let idx = self.profile.string_table.len() as i64;
+        self.profile.string_table.push(s.to_string());
+        self.strings.insert(s.to_string(), idx);
+        idx
+    }
+
+    /// merge the serialized profile, gzip compressed or not, its samples get a `source`
+    /// label with `source` unless they already have one, like the samples of a profile
+    /// merged before. A profile failing the checks is rejected whole, the merger is left
+    /// as it was.
+    pub fn add(&mut self, data: &[u8], source: Option<&str>) -> io::Result<()> {
+        self.add_profile(&parse_profile(data)?, source)
+    }
+
+    /// check the string indexes and the ids of `profile`, and its types against the
+    /// profiles merged before.
+    fn check(&self, profile: &Profile) -> io::Result<()> {
+        let string = |idx: i64| -> io::Result<&str> {
+            usize::try_from(idx)
+                .ok()
+                .and_then(|idx| profile.string_table.get(idx))
+                .map(String::as_str)
+                .ok_or_else(|| invalid_data(format!("string index {idx} out of the table")))
+        };
+        let merged = |idx: i64| self.profile.string_table[idx as usize].as_str();
+        if self.profiles > 0 {
+            let sample_types = profile
+                .sample_type
+                .iter()
+                .map(|t| Ok((string(t.type_)?, string(t.unit)?)))
+                .collect::<io::Result<Vec<_>>>()?;
+            if !sample_types.iter().copied().eq(self
+                .profile
+                .sample_type
+                .iter()
+                .map(|t| (merged(t.type_), merged(t.unit))))
+            {
+                return Err(invalid_data("the sample types of the profiles differ"));
+            }
+            let period_type = profile
+                .period_type
+                .as_ref()
+                .map(|t| Ok::<_, io::Error>((string(t.type_)?, string(t.unit)?)))
+                .transpose()?;
+            let merged_period_type = self
+                .profile
+                .period_type
+                .as_ref()
+                .map(|t| (merged(t.type_), merged(t.unit)));
+            if period_type != merged_period_type {
+                return Err(invalid_data("the period types of the profiles differ"));
+            }
+        }
+
+        for idx in [
+            profile.drop_frames,
+            profile.keep_frames,
+            profile.default_sample_type,
+        ]
+        .iter()
+        .chain(&profile.comment)
+        {
+            string(*idx)?;
+        }
+        for mapping in &profile.mapping {
+            string(mapping.filename)?;
+            string(mapping.build_id)?;
+        }
+        for func in &profile.function {
+            string(func.name)?;
+            string(func.system_name)?;
+            string(func.filename)?;
+        }
+        let mappings: HashSet<u64> = profile.mapping.iter().map(|m| m.id).collect();
+        let functions: HashSet<u64> = profile.function.iter().map(|f| f.id).collect();
+        for loc in &profile.location {
+            if loc.mapping_id != 0 && !mappings.contains(&loc.mapping_id) {
+                return Err(invalid_data(format!(
+                    "unknown mapping id {}",
+                    loc.mapping_id
+                )));
+            }
+            if let Some(line) = loc
+                .line
+                .iter()
+                .find(|line| !functions.contains(&line.function_id))
+            {
+                return Err(invalid_data(format!(
+                    "unknown function id {}",
+                    line.function_id
+                )));
+            }
+        }
+        let locations: HashSet<u64> = profile.location.iter().map(|l| l.id).collect();
+        for sample in &profile.sample {
+            if sample.value.len() != profile.sample_type.len() {
+                return Err(invalid_data(format!(
+                    "a sample has {} values for {} sample types",
+                    sample.value.len(),
+                    profile.sample_type.len()
+                )));
+            }
+            if let Some(id) = sample.location_id.iter().find(|id| !locations.contains(id)) {
+                return Err(invalid_data(format!("unknown location id {id}")));
+            }
+            for label in &sample.label {
+                string(label.key)?;
+                string(label.str)?;
+                string(label.num_unit)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn add_profile(
+        &mut self,
+        profile: &Profile,
+        source: Option<&str>,
+    ) -> io::Result<()> {
+        // a profile failing the checks changes nothing, the merger goes on with the next.
+        self.check(profile)?;
+        let strings: Vec<i64> = profile
+            .string_table
+            .iter()
+            .map(|s| self.string(s))
+            .collect();
+        let string = |idx: i64| -> io::Result<i64> {
+            usize::try_from(idx)
+                .ok()
+                .and_then(|idx| strings.get(idx))
+                .copied()
+                .ok_or_else(|| invalid_data(format!("string index {idx} out of the table")))
+        };
+        let value_type = |t: &ValueType| -> io::Result<ValueType> {
+            Ok(ValueType {
+                type_: string(t.type_)?,
+                unit: string(t.unit)?,
+                ..Default::default()
+            })
+        };
+
+        let sample_type = profile
+            .sample_type
+            .iter()
+            .map(value_type)
+            .collect::<io::Result<Vec<_>>>()?;
+        let period_type = profile.period_type.as_ref().map(value_type).transpose()?;
+        if self.profiles == 0 {
+            self.profile.sample_type = sample_type;
+            self.profile.period_type = period_type.into();
+            self.profile.drop_frames = string(profile.drop_frames)?;
+            self.profile.keep_frames = string(profile.keep_frames)?;
+            self.profile.default_sample_type = string(profile.default_sample_type)?;
+            self.profile.time_nanos = profile.time_nanos;
+        } else {
+            self.profile.time_nanos = self.profile.time_nanos.min(profile.time_nanos);
+        }
+        self.profiles += 1;
+        // the merged profile spans all the profiles.
+        self.end_nanos = self
+            .end_nanos
+            .max(profile.time_nanos.saturating_add(profile.duration_nanos));
+        self.profile.duration_nanos = self.end_nanos.saturating_sub(self.profile.time_nanos);
+        self.profile.period = self.profile.period.max(profile.period);
+        for comment in &profile.comment {
+            let comment = string(*comment)?;
+            if !self.profile.comment.contains(&comment) {
+                self.profile.comment.push(comment);
+            }
+        }
+
+        let mut mapping_ids = HashMap::with_capacity(profile.mapping.len());
+        for mapping in &profile.mapping {
+            let id = self.mapping(Mapping {
+                filename: string(mapping.filename)?,
+                build_id: string(mapping.build_id)?,
+                ..mapping.clone()
+            });
+            mapping_ids.insert(mapping.id, id);
+        }
+        let mut function_ids = HashMap::with_capacity(profile.function.len());
+        for func in &profile.function {
+            let id = self.function(Function {
+                name: string(func.name)?,
+                system_name: string(func.system_name)?,
+                filename: string(func.filename)?,
+                ..func.clone()
+            });
+            function_ids.insert(func.id, id);
+        }
+        let mut location_ids = HashMap::with_capacity(profile.location.len());
+        for loc in &profile.location {
+            let mapping_id = match loc.mapping_id {
+                0 => 0,
+                id => *mapping_ids
+                    .get(&id)
+                    .ok_or_else(|| invalid_data(format!("unknown mapping id {id}")))?,
+            };
+            let line = loc
+                .line
+                .iter()
+                .map(|line| {
+                    let function_id = *function_ids.get(&line.function_id).ok_or_else(|| {
+                        invalid_data(format!("unknown function id {}", line.function_id))
+                    })?;
+                    Ok(Line {
+                        function_id,
+                        ..line.clone()
+                    })
+                })
+                .collect::<io::Result<_>>()?;
+            let id = self.location(Location {
+                mapping_id,
+                line,
+                ..loc.clone()
+            });
+            location_ids.insert(loc.id, id);
+        }
+
+        let source = source.map(|source| (self.string(SOURCE_LABEL), self.string(source)));
+        for sample in &profile.sample {
+            let location_id = sample
+                .location_id
+                .iter()
+                .map(|id| {
+                    location_ids
+                        .get(id)
+                        .copied()
+                        .ok_or_else(|| invalid_data(format!("unknown location id {id}")))
+                })
+                .collect::<io::Result<_>>()?;
+            let mut label = sample
+                .label
+                .iter()
+                .map(|label| {
+                    Ok(Label {
+                        key: string(label.key)?,
+                        str: string(label.str)?,
+                        num_unit: string(label.num_unit)?,
+                        ..label.clone()
+                    })
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            if let Some((key, str)) = source
+                && !label.iter().any(|label| label.key == key)
+            {
+                label.push(Label {
+                    key,
+                    str,
+                    ..Default::default()
+                });
+            }
+            self.sample(Sample {
+                location_id,
+                value: sample.value.clone(),
+                label,
+                ..Default::default()
+            });
+        }
+        Ok(())
+    }
+
+    fn mapping(&mut self, mapping: Mapping) -> u64 {
+        let key = (
+            mapping.memory_start,
+            mapping.memory_limit,
+            mapping.file_offset,
+            mapping.filename,
+            mapping.build_id,
+        );
+        if let Some(id) = self.mappings.get(&key) {
+            return *id;
+        }
+        let id = self.profile.mapping.len() as u64 + 1;
+        self.profile.mapping.push(Mapping { id, ..mapping });
+        self.mappings.insert(key, id);
+        id
+    }
+
+    fn function(&mut self, func: Function) -> u64 {
+        let key = (func.name, func.system_name, func.filename, func.start_line);
+        if let Some(id) = self.functions.get(&key) {
+            return *id;
+        }
+        let id = self.profile.function.len() as u64 + 1;
+        self.profile.function.push(Function { id, ..func });
+        self.functions.insert(key, id);
+        id
+    }
+
+    fn location(&mut self, loc: Location) -> u64 {
+        let key = (
+            loc.mapping_id,
+            loc.address,
+            loc.line.iter().map(|l| (l.function_id, l.line)).collect(),
+            loc.is_folded,
+        );
+        if let Some(id) = self.locations.get(&key) {
+            return *id;
+        }
+        let id = self.profile.location.len() as u64 + 1;
+        self.profile.location.push(Location { id, ..loc });
+        self.locations.insert(key, id);
+        id
+    }
+
+    fn sample(&mut self, sample: Sample) {
+        let labels = sample
+            .label
+            .iter()
+            .map(|l| (l.key, l.str, l.num, l.num_unit))
+            .collect();
+        let key = (sample.location_id.clone(), labels);
+        match self.samples.get(&key) {
+            Some(idx) => {
+                let merged = &mut self.profile.sample[*idx];
+                for (merged, value) in merged.value.iter_mut().zip(&sample.value) {
+                    *merged = merged.saturating_add(*value);
+                }
+            }
+            None => {
+                self.samples.insert(key, self.profile.sample.len());
+                self.profile.sample.push(sample);
+            }
+        }
+    }
+
+    /// write the merged profile in the pprof format, not compressed.
+    pub fn write_to<W: Write>(self, mut writer: W) -> io::Result<()> {
+        self.profile.write_to_writer(&mut writer)?;
+        writer.flush()
+    }
+
+    #[cfg(feature = "http")]
+    pub(crate) fn finish(self) -> Profile {
+        self.profile
+    }
+}
+
+/// Merge the serialized profiles without the `source` labels into `writer`, see
+/// [`ProfileMerger`].
+pub fn merge_profiles<'a, W: Write>(
+    profiles: impl IntoIterator<Item = &'a [u8]>,
+    writer: W,
+) -> io::Result<()> {
+    let mut merger = ProfileMerger::new();
+    for data in profiles {
+        merger.add(data, None)?;
+    }
+    merger.write_to(writer)
+}
@@ -1,4 +1,24 @@
+mod merge;
 mod profile_proto;
 mod writer;
 
+use std::io;
+
+use protobuf::Message;
+
+use crate::gzip;
+
+pub use merge::{ProfileMerger, SOURCE_LABEL, merge_profiles};
+pub use profile_proto::Profile;
+#[cfg(feature = "http")]
+pub use profile_proto::ValueType;
 pub use writer::ProfileProtoWriter;
+
+/// parse a serialized profile, gzip compressed or not.
+pub fn parse_profile(data: &[u8]) -> io::Result<Profile> {
+    if gzip::is_gzip(data) {
+        Ok(Profile::parse_from_bytes(&gzip::decompress(data)?)?)
+    } else {
+        Ok(Profile::parse_from_bytes(data)?)
+    }
+}
@@ -1,23 +1,46 @@
-use std::{collections::HashMap, io::Write, os::raw::c_void};
+use std::{
+    collections::HashMap,
+    io::{self, BufWriter, Write},
+    os::raw::c_void,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use protobuf::{CodedOutputStream, Message};
+use protobuf::{CodedOutputStream, Message, rt::WireType};
 
 use crate::{
-    profile_proto::profile_proto::{Function, Label, Line, Location, Profile, Sample, ValueType},
-    profiler::{AllocSymbolFrames, Symbol},
+    config::ProfAllocConfig,
+    process,
+    profile_proto::profile_proto::{Function, Label, Line, Location, Sample, ValueType},
+    profiler::{AllocSymbolFrames, Symbol, SymbolFramesWriter},
 };
 
+/// the field numbers of `Profile` in profile.proto.
+const SAMPLE_TYPE: u32 = 1;
+const SAMPLE: u32 = 2;
+const LOCATION: u32 = 4;
+const FUNCTION: u32 = 5;
+const STRING_TABLE: u32 = 6;
+const TIME_NANOS: u32 = 9;
+const DURATION_NANOS: u32 = 10;
+const PERIOD_TYPE: u32 = 11;
+const PERIOD: u32 = 12;
+const COMMENT: u32 = 13;
+
+/// The interned strings, the new ones wait in `pending` until they are written.
 struct StringsTable {
-    table: Vec<String>,
+    len: usize,
     cache: HashMap<String, usize>,
+    pending: Vec<String>,
 }
 
 impl StringsTable {
     fn new() -> Self {
         let mut st = Self {
-            table: vec![],
+            len: 0,
             cache: HashMap::new(),
+            pending: Vec::new(),
         };
+        // the index 0 of the string table must be the empty string.
         st.add(String::new());
         st
     }
@@ -28,138 +51,280 @@ impl StringsTable {
         match self.cache.get(&s) {
             Some(idx) => *idx,
             None => {
-                let idx = self.table.len();
-                self.table.push(s.clone());
+                let idx = self.push(s.clone());
                 self.cache.insert(s, idx);
                 idx
             }
         }
     }
+
+    /// add a string that is known to be unique, like the address of a block,
+    /// without keeping it around for the deduplication.
+    fn push(&mut self, s: String) -> usize {
+        let idx = self.len;
+        self.len += 1;
+        self.pending.push(s);
+        idx
+    }
 }
 
 #[derive(Default)]
 struct FuncsTable {
-    table: Vec<Function>,
-    index: HashMap<*mut c_void, usize>,
-}
-
-enum FuncationAdd {
-    Exist(usize),
-    Insert(usize),
+    len: u64,
+    index: HashMap<(i64, i64), u64>,
+    pending: Vec<Function>,
 }
 
 impl FuncsTable {
-    pub fn add(&mut self, strings: &mut StringsTable, symbol: Symbol) -> FuncationAdd {
-        match self.index.get(&symbol.addr) {
-            Some(index) => FuncationAdd::Exist(*index),
+    pub fn add(&mut self, strings: &mut StringsTable, symbol: &Symbol) -> u64 {
+        let name = strings.add(symbol.name.clone()) as i64;
+        let filename = strings.add(symbol.file_name.clone()) as i64;
+        match self.index.get(&(name, filename)) {
+            Some(index) => *index,
             None => {
-                let index = self.table.len() + 1;
-                let name = strings.add(symbol.name) as _;
-                let filename = strings.add(symbol.file_name) as _;
+                self.len += 1;
+                let index = self.len;
                 let func = Function {
-                    id: index as _,
+                    id: index,
                     name,
                     system_name: name,
                     filename,
                     start_line: symbol.line_no as _,
                     special_fields: Default::default(),
                 };
-                self.table.push(func);
-                self.index.insert(symbol.addr, index);
-                FuncationAdd::Insert(index)
+                self.pending.push(func);
+                self.index.insert((name, filename), index);
+                index
             }
         }
     }
 }
 
+/// one location per frame address and line, the inlined functions of the same address
+/// get their own location.
+#[derive(Default)]
+struct LocsTable {
+    len: u64,
+    index: HashMap<(*mut c_void, u64, i64), u64>,
+    pending: Vec<Location>,
+}
+
+impl LocsTable {
+    fn add(&mut self, address: *mut c_void, function_id: u64, line_no: i64) -> u64 {
+        let key = (address, function_id, line_no);
+        if let Some(index) = self.index.get(&key) {
+            return *index;
+        }
+        self.len += 1;
+        let index = self.len;
+        let line = Line {
+            function_id,
+            line: line_no,
+            ..Default::default()
+        };
+        self.pending.push(Location {
+            id: index,
+            line: vec![line],
+            address: address as u64,
+            ..Default::default()
+        });
+        self.index.insert(key, index);
+        index
+    }
+}
+
+/// Writes the fields of a `Profile` one by one as they come, the repeated fields
+/// of the protobuf message can be spread over the stream in any order, only the
+/// order of the elements of each field matters.
+struct ProfileStream<T: Write> {
+    writer: BufWriter<T>,
+    buf: Vec<u8>,
+}
+
+impl<T: Write> ProfileStream<T> {
+    fn encode(
+        &mut self,
+        encode: impl FnOnce(&mut CodedOutputStream<'_>) -> protobuf::Result<()>,
+    ) -> io::Result<()> {
+        self.buf.clear();
+        let mut stream = CodedOutputStream::vec(&mut self.buf);
+        encode(&mut stream)?;
+        stream.flush()?;
+        drop(stream);
+        self.writer.write_all(&self.buf)
+    }
+
+    fn write_message<M: Message>(&mut self, field: u32, msg: &M) -> io::Result<()> {
+        self.encode(|stream| {
+            stream.write_tag(field, WireType::LengthDelimited)?;
+            stream.write_message_no_tag(msg)
+        })
+    }
+
+    fn write_string(&mut self, field: u32, s: &str) -> io::Result<()> {
+        self.encode(|stream| stream.write_string(field, s))
+    }
+
+    fn write_int64(&mut self, field: u32, value: i64) -> io::Result<()> {
+        // the default values are left out, as protobuf does.
+        if value == 0 {
+            return Ok(());
+        }
+        self.encode(|stream| stream.write_int64(field, value))
+    }
+}
+
+/// Writes the tracked blocks as a pprof `Profile` while they are visited, only the
+/// deduplication indexes of the strings, functions and locations are kept in memory.
 pub struct ProfileProtoWriter<T: Write> {
     strings_table: StringsTable,
     funcs_table: FuncsTable,
-    loc_table: Vec<Location>,
-    samples: Vec<Sample>,
-    writer: T,
+    loc_table: LocsTable,
+    comments: Vec<i64>,
+    time: Duration,
+    duration: Duration,
+    sample_rate: usize,
+    capture_labels: bool,
+    size_labels: bool,
+    stream: ProfileStream<T>,
 }
 
 impl<T: Write> ProfileProtoWriter<T> {
-    pub(crate) fn new(writer: T) -> Self {
-        Self {
+    pub(crate) fn new(writer: T, config: &ProfAllocConfig) -> Self {
+        let mut proto_writer = Self {
             strings_table: StringsTable::new(),
             funcs_table: Default::default(),
-            loc_table: Vec::new(),
-            samples: Vec::new(),
-            writer,
+            loc_table: Default::default(),
+            comments: Vec::new(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default(),
+            duration: Duration::ZERO,
+            sample_rate: config.sample_rate,
+            capture_labels: config.capture_labels,
+            size_labels: config.size_labels,
+            stream: ProfileStream {
+                writer: BufWriter::new(writer),
+                buf: Vec::new(),
+            },
+        };
+        for comment in process::comments(config) {
+            proto_writer.add_comment(comment);
         }
+        proto_writer
+    }
+
+    /// the time since the profiler start, written as the duration of the profile.
+    pub(crate) fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
     }
 
-    pub(crate) fn write_symbol_frame(&mut self, symbol_frame: AllocSymbolFrames) {
-        let AllocSymbolFrames { ptr, size, frames } = symbol_frame;
+    /// add a free-form comment to the profile.
+    pub(crate) fn add_comment(&mut self, comment: String) {
+        let idx = self.strings_table.add(comment);
+        self.comments.push(idx as _);
+    }
+
+    /// write the strings, functions and locations interned since the last call.
+    fn write_pending(&mut self) -> io::Result<()> {
+        for s in self.strings_table.pending.drain(..) {
+            self.stream.write_string(STRING_TABLE, &s)?;
+        }
+        for func in self.funcs_table.pending.drain(..) {
+            self.stream.write_message(FUNCTION, &func)?;
+        }
+        for loc in self.loc_table.pending.drain(..) {
+            self.stream.write_message(LOCATION, &loc)?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Write> SymbolFramesWriter for ProfileProtoWriter<T> {
+    fn write_symbol_frame(&mut self, symbol_frame: AllocSymbolFrames<'_>) -> io::Result<()> {
+        let AllocSymbolFrames {
+            ptr,
+            size,
+            age,
+            frames,
+        } = symbol_frame;
         let mut locs = Vec::<u64>::new();
 
         for frame in frames {
-            let line_no = frame.line_no as i64;
-            let address = frame.addr as u64;
-            let func_index = match self.funcs_table.add(&mut self.strings_table, frame) {
-                FuncationAdd::Exist(idx) => {
-                    locs.push(idx as _);
-                    continue;
-                }
-                FuncationAdd::Insert(idx) => {
-                    locs.push(idx as _);
-                    idx as u64
-                }
-            };
-            let line = Line {
-                function_id: func_index,
-                line: line_no,
+            let function_id = self.funcs_table.add(&mut self.strings_table, frame);
+            locs.push(
+                self.loc_table
+                    .add(frame.addr, function_id, frame.line_no as i64),
+            );
+        }
+        let mut label = Vec::new();
+        if self.capture_labels {
+            label.push(Label {
+                key: self.strings_table.add("alloc".into()) as _,
+                str: self.strings_table.push(format!("{:p}", ptr)) as _,
                 ..Default::default()
-            };
-
-            let loc = Location {
-                id: func_index,
-                line: vec![line],
-                address: address,
+            });
+            // `pprof -tagfocus=age_ms=3600000:` keeps the blocks older than an hour.
+            label.push(Label {
+                key: self.strings_table.add("age_ms".into()) as _,
+                num: age.as_millis() as i64,
+                num_unit: self.strings_table.add("ms".into()) as _,
                 ..Default::default()
-            };
-            self.loc_table.push(loc);
+            });
         }
-        let lab = Label {
-            key: self.strings_table.add("alloc".into()) as _,
-            str: self.strings_table.add(format!("{:p}", ptr)) as _,
-            ..Default::default()
-        };
+        if self.size_labels {
+            label.push(Label {
+                key: self.strings_table.add("bytes".into()) as _,
+                num: size as i64,
+                num_unit: self.strings_table.add("bytes".into()) as _,
+                ..Default::default()
+            });
+        }
+        // scale back the sampled allocations.
         let sample = Sample {
             location_id: locs,
-            label: vec![lab],
-            value: vec![size as i64],
+            label,
+            value: vec![
+                (size as u64)
+                    .saturating_mul(self.sample_rate as u64)
+                    .min(i64::MAX as u64) as i64,
+            ],
             ..Default::default()
         };
-        self.samples.push(sample);
+        self.write_pending()?;
+        self.stream.write_message(SAMPLE, &sample)
     }
 
-    pub(crate) fn flush(self) -> std::io::Result<()> {
-        let Self {
-            mut strings_table,
-            funcs_table,
-            loc_table,
-            samples,
-            mut writer,
-        } = self;
+    fn flush(mut self) -> io::Result<()> {
         let samples_value = ValueType {
-            type_: strings_table.add("space".into()) as _,
-            unit: strings_table.add("bytes".into()) as _,
+            type_: self.strings_table.add("space".into()) as _,
+            unit: self.strings_table.add("bytes".into()) as _,
             ..Default::default()
         };
 
-        let profile = Profile {
-            sample_type: vec![samples_value],
-            sample: samples,
-            string_table: strings_table.table,
-            function: funcs_table.table,
-            location: loc_table,
+        let period_type = ValueType {
+            type_: self.strings_table.add("allocations".into()) as _,
+            unit: self.strings_table.add("count".into()) as _,
             ..Default::default()
         };
-        let mut stream = CodedOutputStream::new(&mut writer);
-        profile.write_to_writer(&mut stream)?;
-        Ok(())
+        self.write_pending()?;
+
+        let Self {
+            comments,
+            time,
+            duration,
+            sample_rate,
+            mut stream,
+            ..
+        } = self;
+        stream.write_message(SAMPLE_TYPE, &samples_value)?;
+        stream.write_message(PERIOD_TYPE, &period_type)?;
+        stream.write_int64(PERIOD, sample_rate as i64)?;
+        if !comments.is_empty() {
+            stream.encode(|stream| stream.write_repeated_packed_int64(COMMENT, &comments))?;
+        }
+        stream.write_int64(TIME_NANOS, time.as_nanos() as i64)?;
+        stream.write_int64(DURATION_NANOS, duration.as_nanos() as i64)?;
+        stream.writer.flush()
     }
 }
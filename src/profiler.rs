@@ -3,18 +3,58 @@ use std::{
     cell::{Cell, UnsafeCell},
     collections::HashMap,
     ffi::c_void,
-    io::{self, Write},
+    io,
     mem::MaybeUninit,
-    sync::{Mutex, Once},
-    time::{SystemTime, UNIX_EPOCH},
+    sync::{
+        Mutex, Once, OnceLock,
+        atomic::{AtomicU8, AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
-use crate::{entry::AllocEntry, profile_proto::ProfileProtoWriter};
+use crate::{
+    budget::{Budget, BudgetTable, BudgetViolation},
+    config::ProfAllocConfig,
+    entry::AllocEntry,
+    frame::Frame,
+    histogram::{SizeHistogram, SizeHistogramCounters},
+    lifetime::{LifetimeReport, SiteLifetime},
+    metrics::{HeapMetrics, MetricsCounters},
+    stacks::{GlobalStats, SiteStats, StackId, StackTable},
+    trace::TRACER,
+};
 
 thread_local! {
-    static LOCKED:Cell<bool> = Cell::new(false);
+    static LOCKED:Cell<bool> = const { Cell::new(false) };
+    // the allocations since the last sampled one.
+    static SAMPLE_COUNTER: Cell<usize> = const { Cell::new(0) };
 }
 
+/// Symbol prefixes of the allocation machinery, frames matching them are dropped from the top of the stack.
+pub const DEFAULT_SKIP_PREFIXES: &[&str] = &[
+    "backtrace::",
+    "prof_mem::",
+    "<prof_mem::",
+    "__rust_alloc",
+    "__rust_realloc",
+    "__rust_dealloc",
+    "__rustc",
+    "__rdl_",
+    "__rg_",
+    "core::alloc::",
+    "<core::alloc::",
+    "std::alloc::",
+    "<std::alloc::",
+    "alloc::",
+    "<alloc::",
+    "<[_]>::",
+    "<str>::",
+    "hashbrown::",
+    "<hashbrown::",
+    "std::collections::",
+    "<std::collections::",
+];
+
 pub(crate) struct LockGuard<'a>(Option<std::sync::MutexGuard<'a, ()>>);
 
 impl<'a> Drop for LockGuard<'a> {
@@ -29,44 +69,133 @@ impl<'a> Drop for LockGuard<'a> {
     }
 }
 
-/// alloc frame
-struct AllocFrames {
+/// a tracked block.
+struct AllocBlock {
     size: usize,
-    frames: Vec<*mut c_void>,
+    stack: StackId,
+    // nanoseconds since the profiler start.
+    alloc_time: u64,
 }
 
-pub(crate) struct AllocSymbolFrames {
+pub(crate) struct AllocSymbolFrames<'a> {
     pub(crate) ptr: *const u8,
     pub(crate) size: usize,
-    pub(crate) frames: Vec<Symbol>,
+    /// the time since the block was allocated.
+    pub(crate) age: Duration,
+    pub(crate) frames: &'a [Symbol],
+}
+
+/// The output formats of the tracked blocks, see `crate::dump`.
+pub(crate) trait SymbolFramesWriter {
+    fn write_symbol_frame(&mut self, symbol_frame: AllocSymbolFrames<'_>) -> io::Result<()>;
+
+    fn flush(self) -> io::Result<()>;
 }
 
 pub(crate) struct Symbol {
     pub(crate) file_name: String,
     pub(crate) line_no: u32,
-    #[allow(dead_code)]
     pub(crate) col_no: u32,
     pub(crate) name: String,
     pub(crate) addr: *mut c_void,
 }
 
+/// An allocation site, the frames are innermost first.
+pub(crate) struct ResolvedSite {
+    pub(crate) id: StackId,
+    pub(crate) frames: Vec<Symbol>,
+    pub(crate) stats: SiteStats,
+}
+
+/// the bits of `HeapProfiler::enabled`, without `ENABLED_SET` the config decides.
+const ENABLED: u8 = 1;
+const ENABLED_SET: u8 = 2;
+
 pub(crate) struct HeapProfiler {
-    max_deep: Cell<usize>,
+    config: OnceLock<ProfAllocConfig>,
+    enabled: AtomicU8,
     init_once: Once,
-    frames: UnsafeCell<MaybeUninit<HashMap<*const u8, AllocFrames>>>,
+    start: UnsafeCell<MaybeUninit<Instant>>,
+    blocks: UnsafeCell<MaybeUninit<HashMap<*const u8, AllocBlock>>>,
+    // the length of `blocks`, read without the lock.
+    block_count: AtomicUsize,
+    stacks: UnsafeCell<MaybeUninit<StackTable>>,
+    // whether the function starting at the address belongs to the allocation machinery.
+    internal_funcs: UnsafeCell<MaybeUninit<HashMap<usize, bool>>>,
+    size_histogram: UnsafeCell<SizeHistogramCounters>,
+    metrics: UnsafeCell<MetricsCounters>,
+    budgets: UnsafeCell<BudgetTable>,
 }
 
 impl HeapProfiler {
+    /// Apply the config and create the tables, only the first call takes effect.
     #[inline]
-    fn init_once(&self, max_deep: Option<usize>) {
+    pub(crate) fn init_once(&self, config: &ProfAllocConfig) {
         self.init_once.call_once(|| {
             unsafe {
-                self.max_deep.set(max_deep.unwrap());
-                (&mut *self.frames.get()).write(HashMap::new());
+                let _ = self.config.set(*config);
+                (&mut *self.start.get()).write(Instant::now());
+                (&mut *self.blocks.get()).write(HashMap::new());
+                (&mut *self.stacks.get()).write(StackTable::default());
+                (&mut *self.internal_funcs.get()).write(HashMap::new());
             };
         });
     }
 
+    #[inline(always)]
+    fn initialized(&self) -> bool {
+        self.init_once.is_completed()
+    }
+
+    /// The config of the allocator, the default one before the first allocation.
+    #[inline(always)]
+    pub(crate) fn config(&self) -> ProfAllocConfig {
+        self.config.get().copied().unwrap_or(ProfAllocConfig::new())
+    }
+
+    #[inline(always)]
+    pub(crate) fn is_enabled(&self) -> bool {
+        let enabled = self.enabled.load(Ordering::Relaxed);
+        if enabled & ENABLED_SET != 0 {
+            enabled & ENABLED != 0
+        } else {
+            self.config().enabled
+        }
+    }
+
+    /// The monotonic nanoseconds since the profiler start.
+    #[inline(always)]
+    pub(crate) fn now(&self) -> u64 {
+        unsafe { (*self.start.get()).assume_init_ref() }
+            .elapsed()
+            .as_nanos() as u64
+    }
+
+    /// The time since the profiler start.
+    pub(crate) fn uptime(&self) -> Duration {
+        if !self.initialized() {
+            return Duration::ZERO;
+        }
+        Duration::from_nanos(self.now())
+    }
+
+    /// Whether the current allocation of the thread is sampled, one of every `sample_rate`.
+    #[inline(always)]
+    pub(crate) fn sampled(&self) -> bool {
+        let sample_rate = self.config().sample_rate;
+        if sample_rate <= 1 {
+            return true;
+        }
+        let count = SAMPLE_COUNTER.get() + 1;
+        if count < sample_rate {
+            SAMPLE_COUNTER.set(count);
+            false
+        } else {
+            SAMPLE_COUNTER.set(0);
+            true
+        }
+    }
+
     /// Try to acquire the lock. If it is not available, wait until another thread releases it.
     /// Acquire a  global re-entrant lock over
     /// this lock can be acquired as many times as you want on a single thread without deadlocking, allowing one thread
@@ -82,28 +211,24 @@ impl HeapProfiler {
     }
 
     #[inline(always)]
-    fn trace_frames(&self) -> Vec<*mut c_void> {
+    pub(crate) fn trace_frames(&self) -> Vec<*mut c_void> {
         let _guard = self.lock();
         let mut stack = Vec::new();
+        let mut skipping = true;
         unsafe {
-            let mut skip = 0;
-
             backtrace::trace_unsynchronized(|f| {
-                // skip the call in alloc.
-                // backtrace::backtrace::libunwind::trace::h08cd42aca7d0c759
-                //prof_mem::profiler::HeapProfiler::trace_frames::h8cb48184406ee182
-                //__rustc[4794b31dd7191200]::__rust_alloc
-                //alloc::alloc::alloc::h39a8c1f0979b4a77
-                if skip < 4 {
-                    skip += 1;
-                    return true;
+                // skip the frames of the allocation machinery, e.g. the tracer itself,
+                // `ProfAlloc::alloc`, `__rust_alloc` and the `alloc` crate wrappers.
+                // the chain depends on inlining and on the alloc/realloc/alloc_zeroed path,
+                // so every leading frame is checked until the first caller frame.
+                if skipping {
+                    if self.is_internal_frame(f) {
+                        return true;
+                    }
+                    skipping = false;
                 }
                 stack.push(f.ip());
-                if stack.len() < self.max_deep.get() {
-                    true
-                } else {
-                    false
-                }
+                stack.len() < self.config().max_depth
             });
         }
         #[cfg(feature = "msg")]
@@ -111,67 +236,349 @@ impl HeapProfiler {
         stack
     }
 
-    fn resolve_frames(&self, f: &[*mut c_void]) -> Vec<Symbol> {
-        f.iter()
-            .filter_map(|addr| {
-                let mut symbol: Option<Symbol> = None;
-                unsafe {
-                    backtrace::resolve_unsynchronized(*addr, |frame| {
-                        if symbol.is_none() {
-                            symbol = Some(frame.into());
-                        }
-                    });
-                }
-                symbol
-            })
-            .collect()
+    /// Check the function of the frame against the skip prefixes, the verdict is cached by function address.
+    fn is_internal_frame(&self, frame: &backtrace::Frame) -> bool {
+        let func_addr = match frame.symbol_address() as usize {
+            0 => frame.ip() as usize,
+            addr => addr,
+        };
+        let internal_funcs = unsafe { (&mut *self.internal_funcs.get()).assume_init_mut() };
+        if let Some(internal) = internal_funcs.get(&func_addr) {
+            return *internal;
+        }
+        // the outermost symbol is the function really containing the frame,
+        // the ones before it are inlined into it.
+        let mut outermost = None;
+        unsafe {
+            backtrace::resolve_frame_unsynchronized(frame, |symbol| {
+                outermost = symbol.name().map(|name| name.to_string());
+            });
+        }
+        let internal = outermost
+            .map(|name| self.is_internal_symbol(&name))
+            .unwrap_or(false);
+        internal_funcs.insert(func_addr, internal);
+        internal
     }
 
-    pub fn write_symbol_frames<T: Write>(
+    /// An impl method matches by its self type or, for `<T as Trait>::f`, by its trait.
+    #[inline]
+    fn is_internal_symbol(&self, name: &str) -> bool {
+        let trait_path = name
+            .strip_prefix('<')
+            .and_then(|name| name.split_once(" as "))
+            .map(|(_, trait_path)| trait_path);
+        self.config().skip_prefixes.iter().any(|prefix| {
+            symbol_starts_with(name, prefix)
+                || trait_path.is_some_and(|trait_path| {
+                    symbol_starts_with(trait_path, prefix)
+                        || prefix
+                            .strip_prefix('<')
+                            .is_some_and(|prefix| symbol_starts_with(trait_path, prefix))
+                })
+        })
+    }
+
+    /// Resolve the frames including the inlined functions, innermost first.
+    /// The leading internal symbols inlined into the caller frame are dropped.
+    pub(crate) fn resolve_frames(&self, f: &[*mut c_void]) -> Vec<Symbol> {
+        // the unsynchronized resolution must not run on two threads at once.
+        let _guard = self.lock();
+        let mut symbols = Vec::new();
+        for addr in f {
+            unsafe {
+                backtrace::resolve_unsynchronized(*addr, |symbol| {
+                    symbols.push(Symbol::new(*addr, symbol));
+                });
+            }
+        }
+        let skip = symbols
+            .iter()
+            .take_while(|symbol| self.is_internal_symbol(&symbol.name))
+            .count();
+        // keep the stack when the whole of it looks internal.
+        if skip < symbols.len() {
+            symbols.drain(..skip);
+        }
+        symbols
+    }
+
+    pub(crate) fn write_symbol_frames<W: SymbolFramesWriter>(
         &self,
-        writer: &mut ProfileProtoWriter<T>,
+        writer: &mut W,
     ) -> io::Result<()> {
         let _guard = self.lock();
-        let alloc_frames = unsafe { (*self.frames.get()).assume_init_ref() };
-        for (ptr, alloc_frame) in alloc_frames.iter() {
+        if !self.initialized() {
+            return Ok(());
+        }
+        let blocks = unsafe { (*self.blocks.get()).assume_init_ref() };
+        let stacks = unsafe { (*self.stacks.get()).assume_init_ref() };
+        let now = self.now();
+        // resolve each stack once, many blocks share it.
+        let mut resolved: HashMap<StackId, Vec<Symbol>> = HashMap::new();
+        for (ptr, block) in blocks.iter() {
+            let frames = resolved
+                .entry(block.stack)
+                .or_insert_with(|| self.resolve_frames(&stacks.site(block.stack).frames));
             writer.write_symbol_frame(AllocSymbolFrames {
-                frames: self.resolve_frames(&alloc_frame.frames),
-                size: alloc_frame.size,
+                frames,
+                size: block.size,
+                age: Duration::from_nanos(now.saturating_sub(block.alloc_time)),
                 ptr: *ptr,
-            });
+            })?;
         }
         Ok(())
     }
 
+    /// Track the block, the budget violations are returned for `budget::notify`.
     #[inline(always)]
-    pub(crate) fn insert(&self, ptr: *const u8, lay: Layout) {
+    pub(crate) fn insert(&self, ptr: *const u8, lay: Layout) -> Vec<BudgetViolation> {
         let _guard = self.lock();
+        let start = self.now();
         let frames = self.trace_frames();
-        unsafe {
-            (&mut *self.frames.get()).assume_init_mut().insert(
-                ptr,
-                AllocFrames {
-                    size: lay.size(),
-                    frames: frames,
-                },
-            );
+        let alloc_time = self.now();
+        let stacks = unsafe { (*self.stacks.get()).assume_init_mut() };
+        let stack = stacks.intern(frames);
+        stacks.alloc(stack, lay.size(), alloc_time);
+        let budgets = unsafe { &mut *self.budgets.get() };
+        let violations = if budgets.is_active() {
+            let bytes = (lay.size() as u64).saturating_mul(self.config().sample_rate as u64);
+            budgets.alloc(self, stack, &stacks.site(stack).frames, bytes)
+        } else {
+            Vec::new()
+        };
+        let size_histogram = unsafe { &mut *self.size_histogram.get() };
+        size_histogram.add(lay.size());
+        let blocks = unsafe { (&mut *self.blocks.get()).assume_init_mut() };
+        let replaced = blocks.insert(
+            ptr,
+            AllocBlock {
+                size: lay.size(),
+                stack,
+                alloc_time,
+            },
+        );
+        self.block_count.store(blocks.len(), Ordering::Relaxed);
+        if let Some(replaced) = replaced {
+            self.free_block(replaced);
         }
+        if TRACER.is_active() {
+            let frames = &stacks.site(stack).frames;
+            TRACER.alloc(alloc_time, ptr, lay.size(), stack, frames);
+        }
+        let metrics = unsafe { &mut *self.metrics.get() };
+        metrics.allocs += 1;
+        metrics.overhead_nanos += self.now().saturating_sub(start);
+        violations
+    }
+
+    /// Whether the block at `ptr` is tracked, the caller holds the lock.
+    pub(crate) fn is_tracked(&self, ptr: *const u8) -> bool {
+        self.block_count.load(Ordering::Relaxed) > 0
+            && unsafe { (*self.blocks.get()).assume_init_ref() }.contains_key(&ptr)
+    }
+
+    /// Whether any block is tracked, without taking the lock.
+    #[inline(always)]
+    pub(crate) fn has_blocks(&self) -> bool {
+        self.block_count.load(Ordering::Relaxed) > 0
     }
 
     #[inline(always)]
     pub(crate) fn remove(&self, ptr: *const u8) {
         let _guard = self.lock();
-        unsafe {
-            (&mut *self.frames.get()).assume_init_mut().remove(&ptr);
+        let start = self.now();
+        let blocks = unsafe { (&mut *self.blocks.get()).assume_init_mut() };
+        let removed = blocks.remove(&ptr);
+        self.block_count.store(blocks.len(), Ordering::Relaxed);
+        if let Some(removed) = removed {
+            if TRACER.is_active() {
+                TRACER.free(self.now(), ptr);
+            }
+            self.free_block(removed);
         }
+        unsafe { &mut *self.metrics.get() }.overhead_nanos += self.now().saturating_sub(start);
     }
+
+    #[inline(always)]
+    fn free_block(&self, block: AllocBlock) {
+        unsafe { (*self.stacks.get()).assume_init_mut() }.free(
+            block.stack,
+            block.size,
+            block.alloc_time,
+            self.now(),
+        );
+        unsafe { &mut *self.size_histogram.get() }.remove(block.size);
+        unsafe { &mut *self.metrics.get() }.frees += 1;
+        let budgets = unsafe { &mut *self.budgets.get() };
+        if budgets.is_active() {
+            let bytes = (block.size as u64).saturating_mul(self.config().sample_rate as u64);
+            budgets.free(block.stack, bytes);
+        }
+    }
+
+    /// Call `f` with the address, the size, the stack, the allocation time and the frames
+    /// of each tracked block.
+    pub(crate) fn for_each_block(
+        &self,
+        mut f: impl FnMut(*const u8, usize, StackId, u64, &[*mut c_void]),
+    ) {
+        let _guard = self.lock();
+        if !self.initialized() {
+            return;
+        }
+        let blocks = unsafe { (*self.blocks.get()).assume_init_ref() };
+        let stacks = unsafe { (*self.stacks.get()).assume_init_ref() };
+        for (ptr, block) in blocks.iter() {
+            let frames = &stacks.site(block.stack).frames;
+            f(*ptr, block.size, block.stack, block.alloc_time, frames);
+        }
+    }
+
+    /// Replace the budgets, the live blocks are counted for the new ones, the violations
+    /// are returned for `budget::notify`.
+    pub(crate) fn set_budgets(&self, budgets: Vec<Budget>) -> Vec<BudgetViolation> {
+        let _guard = self.lock();
+        let table = unsafe { &mut *self.budgets.get() };
+        table.reset(budgets);
+        if !self.initialized() || !table.is_active() {
+            return Vec::new();
+        }
+        let scale = self.config().sample_rate as u64;
+        let stacks = unsafe { (*self.stacks.get()).assume_init_ref() };
+        let mut violations: Vec<BudgetViolation> = Vec::new();
+        for (id, site) in stacks.sites() {
+            if site.stats.live_bytes > 0 {
+                let bytes = site.stats.live_bytes.saturating_mul(scale);
+                violations.extend(table.alloc(self, id, &site.frames, bytes));
+            }
+        }
+        violations
+    }
+
+    pub(crate) fn with_budgets<R>(&self, f: impl FnOnce(&mut BudgetTable) -> R) -> R {
+        let _guard = self.lock();
+        f(unsafe { &mut *self.budgets.get() })
+    }
+
+    /// The allocation sites with their resolved frames, the peak counters are up to date.
+    pub(crate) fn resolved_sites(&self) -> Vec<ResolvedSite> {
+        let _guard = self.lock();
+        if !self.initialized() {
+            return Vec::new();
+        }
+        let stacks = unsafe { (*self.stacks.get()).assume_init_mut() };
+        stacks.record_peak();
+        stacks
+            .sites()
+            .map(|(id, site)| ResolvedSite {
+                id,
+                frames: self.resolve_frames(&site.frames),
+                stats: site.stats,
+            })
+            .collect()
+    }
+
+    /// The age of the oldest live block per stack, in nanoseconds.
+    pub(crate) fn oldest_ages(&self) -> HashMap<StackId, u64> {
+        let _guard = self.lock();
+        let mut oldest: HashMap<StackId, u64> = HashMap::new();
+        if !self.initialized() {
+            return oldest;
+        }
+        let now = self.now();
+        let blocks = unsafe { (*self.blocks.get()).assume_init_ref() };
+        for block in blocks.values() {
+            let age = oldest.entry(block.stack).or_default();
+            *age = (*age).max(now.saturating_sub(block.alloc_time));
+        }
+        oldest
+    }
+
+    pub(crate) fn lifetimes(&self) -> LifetimeReport {
+        let _guard = self.lock();
+        if !self.initialized() {
+            return LifetimeReport::default();
+        }
+        let oldest = self.oldest_ages();
+        let sites = self
+            .resolved_sites()
+            .into_iter()
+            .map(|ResolvedSite { id, frames, stats }| {
+                let mean_lifetime = match stats.freed_blocks {
+                    0 => 0,
+                    n => (stats.freed_lifetime / n as u128) as u64,
+                };
+                SiteLifetime {
+                    frames: frames.iter().map(Frame::from).collect(),
+                    total_blocks: stats.total_blocks,
+                    total_bytes: stats.total_bytes,
+                    freed_blocks: stats.freed_blocks,
+                    live_blocks: stats.live_blocks,
+                    live_bytes: stats.live_bytes,
+                    mean_lifetime: Duration::from_nanos(mean_lifetime),
+                    max_lifetime: Duration::from_nanos(stats.max_lifetime),
+                    oldest_live: Duration::from_nanos(oldest.get(&id).copied().unwrap_or_default()),
+                    histogram: stats.lifetimes,
+                }
+            })
+            .collect();
+        LifetimeReport { sites }
+    }
+
+    pub(crate) fn global_stats(&self) -> GlobalStats {
+        let _guard = self.lock();
+        if !self.initialized() {
+            return GlobalStats::default();
+        }
+        unsafe { (*self.stacks.get()).assume_init_ref() }.global()
+    }
+
+    pub(crate) fn metrics(&self) -> HeapMetrics {
+        let _guard = self.lock();
+        if !self.initialized() {
+            return HeapMetrics::default();
+        }
+        let counters = unsafe { *self.metrics.get() };
+        let global = unsafe { (*self.stacks.get()).assume_init_ref() }.global();
+        let blocks = unsafe { (*self.blocks.get()).assume_init_ref() }.len();
+        let scale = self.config().sample_rate as u64;
+        HeapMetrics {
+            inuse_bytes: global.live_bytes.saturating_mul(scale),
+            allocations: counters.allocs.saturating_mul(scale),
+            frees: counters.frees.saturating_mul(scale),
+            peak_bytes: global.max_bytes.saturating_mul(scale),
+            tracked_blocks: blocks as u64,
+            overhead: Duration::from_nanos(counters.overhead_nanos),
+        }
+    }
+
+    pub(crate) fn size_histogram(&self) -> SizeHistogram {
+        let _guard = self.lock();
+        unsafe { &*self.size_histogram.get() }.snapshot()
+    }
+}
+
+/// Prefix match ignoring the crate disambiguators of v0 symbols, e.g. `alloc[fdfd2bd8633a6659]::raw_vec`.
+fn symbol_starts_with(name: &str, prefix: &str) -> bool {
+    let mut name = name.chars();
+    for expected in prefix.chars() {
+        let mut c = name.next();
+        if c == Some('[') && expected != '[' {
+            c = name.find(|c| *c == ']').and_then(|_| name.next());
+        }
+        if c != Some(expected) {
+            return false;
+        }
+    }
+    true
 }
 
-impl From<&backtrace::Symbol> for Symbol {
+impl Symbol {
     #[inline(always)]
-    fn from(value: &backtrace::Symbol) -> Self {
+    fn new(addr: *mut c_void, value: &backtrace::Symbol) -> Self {
         Self {
-            addr: value.addr().unwrap_or_default(),
+            addr,
             file_name: value
                 .filename()
                 .map(|p| p.to_str().unwrap().to_string())
@@ -186,27 +593,45 @@ impl From<&backtrace::Symbol> for Symbol {
 unsafe impl Send for HeapProfiler {}
 unsafe impl Sync for HeapProfiler {}
 
-pub(crate) fn get_profiler(max_deep: Option<usize>) -> &'static HeapProfiler {
+pub(crate) fn get_profiler() -> &'static HeapProfiler {
     static PROFILER: HeapProfiler = HeapProfiler {
-        max_deep: Cell::new(128),
-        frames: UnsafeCell::new(MaybeUninit::uninit()),
+        config: OnceLock::new(),
+        enabled: AtomicU8::new(0),
+        start: UnsafeCell::new(MaybeUninit::uninit()),
+        blocks: UnsafeCell::new(MaybeUninit::uninit()),
+        block_count: AtomicUsize::new(0),
+        stacks: UnsafeCell::new(MaybeUninit::uninit()),
+        internal_funcs: UnsafeCell::new(MaybeUninit::uninit()),
+        size_histogram: UnsafeCell::new(SizeHistogramCounters::new()),
+        metrics: UnsafeCell::new(MetricsCounters::new()),
+        budgets: UnsafeCell::new(BudgetTable::new()),
         init_once: Once::new(),
     };
-    PROFILER.init_once(max_deep);
-    return &PROFILER;
+    &PROFILER
 }
 
-pub fn dump() -> io::Result<()> {
+/// The power-of-two size classes of the tracked allocations, live and since the start.
+pub fn size_histogram() -> SizeHistogram {
     let _alloc_entry = AllocEntry::new();
-    let time = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e.to_string()))?;
-    let mut file = std::fs::OpenOptions::new()
-        .create(true)
-        .write(true)
-        .open(format!("mem.{}.pb", time.as_millis()))?;
-    let mut writer = ProfileProtoWriter::new(&mut file);
-    let profiler = get_profiler(None);
-    profiler.write_symbol_frames(&mut writer)?;
-    writer.flush()
+    get_profiler().size_histogram()
+}
+
+/// The lifetimes of the tracked blocks per allocation site, see [`LifetimeReport`].
+pub fn lifetimes() -> LifetimeReport {
+    let _alloc_entry = AllocEntry::new();
+    get_profiler().lifetimes()
+}
+
+/// Turn the tracking of new allocations on or off at runtime, the frees are always tracked.
+/// It takes over the `enabled` of the config, even when called before the first allocation.
+pub fn set_enabled(enabled: bool) {
+    get_profiler()
+        .enabled
+        .store(ENABLED_SET | enabled as u8, Ordering::Relaxed);
+}
+
+/// Whether new allocations are tracked, by the last [`set_enabled`] or else by the config
+/// of the allocator, the default config before the first allocation.
+pub fn is_enabled() -> bool {
+    get_profiler().is_enabled()
 }
@@ -0,0 +1,128 @@
+use std::{
+    cmp::Reverse,
+    fmt::Write as _,
+    io::{self, Write},
+};
+
+use crate::{entry::AllocEntry, frame::Frame, profiler::get_profiler};
+
+/// The order of the sites of [`report_top`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+    /// the bytes of the live blocks.
+    #[default]
+    InuseBytes,
+    /// the count of the live blocks.
+    Count,
+    /// the average size of the live blocks.
+    AvgSize,
+    /// the bytes allocated since the start, freed or not.
+    TotalBytes,
+}
+
+/// An allocation site of the top report.
+#[derive(Clone, Debug)]
+pub struct TopSite {
+    pub frames: Vec<Frame>,
+    pub inuse_bytes: u64,
+    pub count: u64,
+    pub total_bytes: u64,
+}
+
+impl TopSite {
+    /// the average size of the live blocks.
+    pub fn avg_size(&self) -> u64 {
+        self.inuse_bytes.checked_div(self.count).unwrap_or_default()
+    }
+}
+
+/// The `n` heaviest allocation sites, the counters are scaled back by the sample rate.
+pub fn top_sites(n: usize, sort_by: SortBy) -> Vec<TopSite> {
+    let _alloc_entry = AllocEntry::new();
+    let profiler = get_profiler();
+    let scale = profiler.config().sample_rate as u64;
+    let mut sites: Vec<_> = profiler
+        .resolved_sites()
+        .into_iter()
+        .filter(|site| match sort_by {
+            SortBy::TotalBytes => site.stats.total_blocks > 0,
+            _ => site.stats.live_blocks > 0,
+        })
+        .map(|site| TopSite {
+            frames: site.frames.iter().map(Frame::from).collect(),
+            inuse_bytes: site.stats.live_bytes.saturating_mul(scale),
+            count: site.stats.live_blocks.saturating_mul(scale),
+            total_bytes: site.stats.total_bytes.saturating_mul(scale),
+        })
+        .collect();
+    sites.sort_by_key(|site| {
+        Reverse(match sort_by {
+            SortBy::InuseBytes => site.inuse_bytes,
+            SortBy::Count => site.count,
+            SortBy::AvgSize => site.avg_size(),
+            SortBy::TotalBytes => site.total_bytes,
+        })
+    });
+    sites.truncate(n);
+    sites
+}
+
+/// A text table of the `n` heaviest allocation sites.
+///
+/// ```text
+///  inuse bytes  inuse%     count   avg size  total bytes  site
+///     10485760  81.23%        10    1048576     10485760  my_crate::load (src/load.rs:12)
+/// ```
+pub fn report_top(n: usize, sort_by: SortBy) -> String {
+    let _alloc_entry = AllocEntry::new();
+    let total_inuse = get_profiler()
+        .global_stats()
+        .live_bytes
+        .saturating_mul(get_profiler().config().sample_rate as u64);
+    let sites = top_sites(n, sort_by);
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "{:>12} {:>7} {:>9} {:>10} {:>12}  site",
+        "inuse bytes", "inuse%", "count", "avg size", "total bytes"
+    );
+    for site in &sites {
+        let percent = match total_inuse {
+            0 => 0.0,
+            total => site.inuse_bytes as f64 * 100.0 / total as f64,
+        };
+        let top = site
+            .frames
+            .first()
+            .map(|frame| {
+                let function = frame.short_function();
+                if frame.file.is_empty() {
+                    function
+                } else {
+                    format!("{function} ({}:{})", frame.file, frame.line)
+                }
+            })
+            .unwrap_or_else(|| "[unknown]".to_string());
+        let _ = writeln!(
+            report,
+            "{:>12} {:>6.2}% {:>9} {:>10} {:>12}  {}",
+            site.inuse_bytes,
+            percent,
+            site.count,
+            site.avg_size(),
+            site.total_bytes,
+            top
+        );
+    }
+    let _ = writeln!(report, "total inuse bytes: {total_inuse}");
+    report
+}
+
+/// Print the [`report_top`] table to stderr, the memory of the report itself is not tracked.
+pub fn print_top(n: usize, sort_by: SortBy) -> io::Result<()> {
+    let _alloc_entry = AllocEntry::new();
+    let report = report_top(n, sort_by);
+    let mut stderr = io::stderr().lock();
+    stderr.write_all(report.as_bytes())?;
+    stderr.flush()
+}
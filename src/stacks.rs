@@ -0,0 +1,165 @@
+use std::{collections::HashMap, ffi::c_void};
+
+use crate::lifetime::LifetimeHistogram;
+
+pub(crate) type StackId = usize;
+
+/// The counters of the blocks allocated from one stack, the times are nanoseconds since the profiler start.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct SiteStats {
+    pub(crate) live_blocks: u64,
+    pub(crate) live_bytes: u64,
+    pub(crate) total_blocks: u64,
+    pub(crate) total_bytes: u64,
+    /// the most live bytes of the site so far, and the live blocks at that time.
+    pub(crate) max_bytes: u64,
+    pub(crate) max_blocks: u64,
+    /// the live bytes and blocks of the site when the total live bytes peaked.
+    pub(crate) gmax_bytes: u64,
+    pub(crate) gmax_blocks: u64,
+    /// the sequence number of the last change of the site.
+    changed: u64,
+    /// the sum of the allocation times of the live blocks.
+    pub(crate) live_alloc_time: u128,
+    pub(crate) freed_blocks: u64,
+    /// the sum of the lifetimes of the freed blocks.
+    pub(crate) freed_lifetime: u128,
+    pub(crate) max_lifetime: u64,
+    pub(crate) lifetimes: LifetimeHistogram,
+}
+
+impl SiteStats {
+    #[inline(always)]
+    fn alloc(&mut self, size: usize, now: u64) {
+        self.live_blocks += 1;
+        self.live_bytes += size as u64;
+        self.total_blocks += 1;
+        self.total_bytes += size as u64;
+        self.live_alloc_time += now as u128;
+        if self.live_bytes > self.max_bytes {
+            self.max_bytes = self.live_bytes;
+            self.max_blocks = self.live_blocks;
+        }
+    }
+
+    #[inline(always)]
+    fn free(&mut self, size: usize, alloc_time: u64, now: u64) {
+        let lifetime = now.saturating_sub(alloc_time);
+        self.live_blocks -= 1;
+        self.live_bytes -= size as u64;
+        self.live_alloc_time -= alloc_time as u128;
+        self.freed_blocks += 1;
+        self.freed_lifetime += lifetime as u128;
+        self.max_lifetime = self.max_lifetime.max(lifetime);
+        self.lifetimes.record(lifetime);
+    }
+
+    /// the sum of the lifetimes of the freed blocks and the ages of the live ones.
+    pub(crate) fn total_lifetime(&self, now: u64) -> u128 {
+        self.freed_lifetime + (self.live_blocks as u128 * now as u128 - self.live_alloc_time)
+    }
+}
+
+/// An allocation site, the captured stack and the counters of its blocks.
+pub(crate) struct Site {
+    pub(crate) frames: Box<[*mut c_void]>,
+    pub(crate) stats: SiteStats,
+}
+
+/// The totals over all the sites.
+#[derive(Clone, Copy, Default)]
+pub(crate) struct GlobalStats {
+    pub(crate) live_bytes: u64,
+    pub(crate) live_blocks: u64,
+    /// the peak of the live bytes, with the live blocks and the time of the peak.
+    pub(crate) max_bytes: u64,
+    pub(crate) max_blocks: u64,
+    pub(crate) max_time: u64,
+}
+
+/// The interned stacks, each block refers to its stack by id.
+#[derive(Default)]
+pub(crate) struct StackTable {
+    index: HashMap<Box<[*mut c_void]>, StackId>,
+    sites: Vec<Site>,
+    global: GlobalStats,
+    // every alloc and free takes a sequence number, a site unchanged since the
+    // peak still holds its counters at the peak.
+    seq: u64,
+    peak_seq: u64,
+}
+
+impl StackTable {
+    #[inline(always)]
+    pub(crate) fn intern(&mut self, frames: Vec<*mut c_void>) -> StackId {
+        if let Some(id) = self.index.get(frames.as_slice()) {
+            return *id;
+        }
+        let id = self.sites.len();
+        let frames = frames.into_boxed_slice();
+        self.index.insert(frames.clone(), id);
+        self.sites.push(Site {
+            frames,
+            stats: Default::default(),
+        });
+        id
+    }
+
+    #[inline(always)]
+    pub(crate) fn alloc(&mut self, id: StackId, size: usize, now: u64) {
+        self.touch(id);
+        self.sites[id].stats.alloc(size, now);
+        let global = &mut self.global;
+        global.live_bytes += size as u64;
+        global.live_blocks += 1;
+        if global.live_bytes > global.max_bytes {
+            global.max_bytes = global.live_bytes;
+            global.max_blocks = global.live_blocks;
+            global.max_time = now;
+            self.peak_seq = self.seq;
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn free(&mut self, id: StackId, size: usize, alloc_time: u64, now: u64) {
+        self.touch(id);
+        self.sites[id].stats.free(size, alloc_time, now);
+        self.global.live_bytes -= size as u64;
+        self.global.live_blocks -= 1;
+    }
+
+    /// Keep the counters of the site at the peak, before its first change after the peak.
+    #[inline(always)]
+    fn touch(&mut self, id: StackId) {
+        self.seq += 1;
+        let stats = &mut self.sites[id].stats;
+        if stats.changed <= self.peak_seq {
+            stats.gmax_bytes = stats.live_bytes;
+            stats.gmax_blocks = stats.live_blocks;
+        }
+        stats.changed = self.seq;
+    }
+
+    /// Record the counters at the peak of the sites unchanged since the peak.
+    pub(crate) fn record_peak(&mut self) {
+        for site in self.sites.iter_mut() {
+            if site.stats.changed <= self.peak_seq {
+                site.stats.gmax_bytes = site.stats.live_bytes;
+                site.stats.gmax_blocks = site.stats.live_blocks;
+            }
+        }
+    }
+
+    #[inline(always)]
+    pub(crate) fn site(&self, id: StackId) -> &Site {
+        &self.sites[id]
+    }
+
+    pub(crate) fn sites(&self) -> impl Iterator<Item = (StackId, &Site)> {
+        self.sites.iter().enumerate()
+    }
+
+    pub(crate) fn global(&self) -> GlobalStats {
+        self.global
+    }
+}
@@ -0,0 +1,78 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The exact counters of every call of the allocator, kept apart from the profiler so
+/// they hold with the tracking disabled, sampled or filtered by size.
+pub(crate) struct StatsCounters {
+    allocated_bytes: AtomicU64,
+    freed_bytes: AtomicU64,
+    allocs: AtomicU64,
+    deallocs: AtomicU64,
+    reallocs: AtomicU64,
+}
+
+pub(crate) static STATS: StatsCounters = StatsCounters {
+    allocated_bytes: AtomicU64::new(0),
+    freed_bytes: AtomicU64::new(0),
+    allocs: AtomicU64::new(0),
+    deallocs: AtomicU64::new(0),
+    reallocs: AtomicU64::new(0),
+};
+
+impl StatsCounters {
+    #[inline(always)]
+    pub(crate) fn alloc(&self, size: usize) {
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        self.allocated_bytes
+            .fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    #[inline(always)]
+    pub(crate) fn dealloc(&self, size: usize) {
+        self.deallocs.fetch_add(1, Ordering::Relaxed);
+        self.freed_bytes.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    /// a moved block counts as the free of the old size and the allocation of the new one.
+    #[inline(always)]
+    pub(crate) fn realloc(&self, old_size: usize, new_size: usize) {
+        self.reallocs.fetch_add(1, Ordering::Relaxed);
+        self.freed_bytes
+            .fetch_add(old_size as u64, Ordering::Relaxed);
+        self.allocated_bytes
+            .fetch_add(new_size as u64, Ordering::Relaxed);
+    }
+}
+
+/// The counters of [`stats`], exact whatever the config of the profiler.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapStats {
+    /// the bytes allocated since the start, the new sizes of the reallocations included.
+    pub allocated_bytes: u64,
+    /// the bytes freed since the start, the old sizes of the reallocations included.
+    pub freed_bytes: u64,
+    pub live_bytes: u64,
+    pub live_blocks: u64,
+    /// the calls of `alloc` and `alloc_zeroed`.
+    pub alloc_calls: u64,
+    pub dealloc_calls: u64,
+    pub realloc_calls: u64,
+}
+
+/// The allocator statistics, counted with atomics on every call, the allocations of the
+/// profiler itself included. The counters are read one by one, a snapshot taken while
+/// other threads allocate may be off by their calls in flight.
+pub fn stats() -> HeapStats {
+    let allocated_bytes = STATS.allocated_bytes.load(Ordering::Relaxed);
+    let freed_bytes = STATS.freed_bytes.load(Ordering::Relaxed);
+    let alloc_calls = STATS.allocs.load(Ordering::Relaxed);
+    let dealloc_calls = STATS.deallocs.load(Ordering::Relaxed);
+    HeapStats {
+        allocated_bytes,
+        freed_bytes,
+        live_bytes: allocated_bytes.saturating_sub(freed_bytes),
+        live_blocks: alloc_calls.saturating_sub(dealloc_calls),
+        alloc_calls,
+        dealloc_calls,
+        realloc_calls: STATS.reallocs.load(Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,648 @@
+use std::{
+    cell::Cell,
+    collections::{HashMap, HashSet},
+    ffi::c_void,
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    mem,
+    path::Path,
+    sync::{
+        Condvar, Mutex, PoisonError,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use crate::{
+    entry::AllocEntry,
+    frame::Frame,
+    invalid_data,
+    profiler::{HeapProfiler, get_profiler},
+    stacks::StackId,
+};
+
+/// the magic of the trace files, the last byte is the version.
+const MAGIC: &[u8; 8] = b"PMTRACE\x01";
+
+// the record tags, each followed by its varint fields.
+const TAG_STRING: u8 = 1;
+const TAG_STACK: u8 = 2;
+const TAG_ALLOC: u8 = 3;
+const TAG_FREE: u8 = 4;
+const TAG_REALLOC: u8 = 5;
+
+/// the pending events waking the writer before its period.
+const WAKE_EVENTS: usize = 1 << 16;
+const WRITE_PERIOD: Duration = Duration::from_millis(100);
+
+thread_local! {
+    // the id of the thread in the traces, 0 until its first event.
+    static TRACE_THREAD: Cell<u32> = const { Cell::new(0) };
+    // the block being reallocated by the thread and whether it was untracked already.
+    static REALLOC_FROM: Cell<Option<(usize, bool)>> = const { Cell::new(None) };
+}
+
+/// An event of a trace, the times are since the profiler start, the thread 0 is the one
+/// of the blocks already live when the recording started.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TraceEvent {
+    Alloc {
+        time: u64,
+        thread: u32,
+        ptr: u64,
+        size: u64,
+        stack: u64,
+    },
+    Free {
+        time: u64,
+        thread: u32,
+        ptr: u64,
+    },
+    Realloc {
+        time: u64,
+        thread: u32,
+        old_ptr: u64,
+        ptr: u64,
+        size: u64,
+        stack: u64,
+    },
+}
+
+impl TraceEvent {
+    /// the time of the event since the profiler start.
+    pub fn time(&self) -> Duration {
+        let (Self::Alloc { time, .. } | Self::Free { time, .. } | Self::Realloc { time, .. }) =
+            self;
+        Duration::from_nanos(*time)
+    }
+}
+
+/// A live block of [`Trace::live_at`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TraceBlock {
+    pub ptr: u64,
+    pub size: u64,
+    pub stack: u64,
+}
+
+/// A trace recorded by [`record_trace`], read back.
+#[derive(Clone, Debug, Default)]
+pub struct Trace {
+    /// the resolved stacks by id, innermost first.
+    pub stacks: HashMap<u64, Vec<Frame>>,
+    /// the events in the order the profiler saw them.
+    pub events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Self::parse(&data)
+    }
+
+    pub fn parse(data: &[u8]) -> io::Result<Self> {
+        let Some(mut data) = data.strip_prefix(MAGIC) else {
+            return Err(invalid_data("not a prof-mem trace"));
+        };
+        let mut strings = HashMap::new();
+        let mut trace = Self::default();
+        while let Some((&tag, rest)) = data.split_first() {
+            data = rest;
+            match tag {
+                TAG_STRING => {
+                    let id = read_varint(&mut data)?;
+                    let len = read_varint(&mut data)? as usize;
+                    if len > data.len() {
+                        return Err(invalid_data("truncated string"));
+                    }
+                    let (bytes, rest) = data.split_at(len);
+                    data = rest;
+                    strings.insert(id, String::from_utf8_lossy(bytes).into_owned());
+                }
+                TAG_STACK => {
+                    let id = read_varint(&mut data)?;
+                    let len = read_varint(&mut data)?;
+                    let mut frames = Vec::new();
+                    for _ in 0..len {
+                        let string = |data: &mut &[u8]| {
+                            let id = read_varint(data)?;
+                            strings
+                                .get(&id)
+                                .cloned()
+                                .ok_or_else(|| invalid_data("unknown string"))
+                        };
+                        frames.push(Frame {
+                            function: string(&mut data)?,
+                            file: string(&mut data)?,
+                            line: read_varint(&mut data)? as u32,
+                            address: read_varint(&mut data)? as usize,
+                        });
+                    }
+                    trace.stacks.insert(id, frames);
+                }
+                TAG_ALLOC => trace.events.push(TraceEvent::Alloc {
+                    time: read_varint(&mut data)?,
+                    thread: read_varint(&mut data)? as u32,
+                    ptr: read_varint(&mut data)?,
+                    size: read_varint(&mut data)?,
+                    stack: read_varint(&mut data)?,
+                }),
+                TAG_FREE => trace.events.push(TraceEvent::Free {
+                    time: read_varint(&mut data)?,
+                    thread: read_varint(&mut data)? as u32,
+                    ptr: read_varint(&mut data)?,
+                }),
+                TAG_REALLOC => trace.events.push(TraceEvent::Realloc {
+                    time: read_varint(&mut data)?,
+                    thread: read_varint(&mut data)? as u32,
+                    old_ptr: read_varint(&mut data)?,
+                    ptr: read_varint(&mut data)?,
+                    size: read_varint(&mut data)?,
+                    stack: read_varint(&mut data)?,
+                }),
+                _ => return Err(invalid_data("unknown record")),
+            }
+        }
+        Ok(trace)
+    }
+
+    /// The tracked blocks live at `time` since the profiler start, by address.
+    pub fn live_at(&self, time: Duration) -> Vec<TraceBlock> {
+        let time = time.as_nanos() as u64;
+        let mut live = HashMap::new();
+        for event in &self.events {
+            match *event {
+                TraceEvent::Alloc {
+                    time: t,
+                    ptr,
+                    size,
+                    stack,
+                    ..
+                } if t <= time => {
+                    live.insert(ptr, TraceBlock { ptr, size, stack });
+                }
+                TraceEvent::Free { time: t, ptr, .. } if t <= time => {
+                    live.remove(&ptr);
+                }
+                TraceEvent::Realloc {
+                    time: t,
+                    old_ptr,
+                    ptr,
+                    size,
+                    stack,
+                    ..
+                } if t <= time => {
+                    live.remove(&old_ptr);
+                    live.insert(ptr, TraceBlock { ptr, size, stack });
+                }
+                _ => {}
+            }
+        }
+        let mut blocks: Vec<_> = live.into_values().collect();
+        blocks.sort_by_key(|block| block.ptr);
+        blocks
+    }
+}
+
+fn read_varint(data: &mut &[u8]) -> io::Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let Some((&byte, rest)) = data.split_first() else {
+            return Err(invalid_data("truncated varint"));
+        };
+        *data = rest;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(invalid_data("varint too long"))
+}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+enum TraceRecord {
+    Stack {
+        id: StackId,
+        frames: Box<[*mut c_void]>,
+    },
+    Alloc {
+        time: u64,
+        thread: u32,
+        ptr: usize,
+        size: usize,
+        stack: StackId,
+    },
+    Free {
+        time: u64,
+        thread: u32,
+        ptr: usize,
+    },
+    Realloc {
+        time: u64,
+        thread: u32,
+        old_ptr: usize,
+        ptr: usize,
+        size: usize,
+        stack: StackId,
+    },
+}
+
+struct TraceState {
+    /// the records not written yet.
+    records: Vec<TraceRecord>,
+    /// the stacks already in the trace.
+    stacks: Option<HashSet<StackId>>,
+    stop: bool,
+}
+
+impl TraceState {
+    fn push_stack(&mut self, id: StackId, frames: &[*mut c_void]) {
+        if self.stacks.get_or_insert_with(HashSet::new).insert(id) {
+            self.records.push(TraceRecord::Stack {
+                id,
+                frames: frames.into(),
+            });
+        }
+    }
+}
+
+// the frames of `TraceRecord::Stack` are addresses the writer thread prints as integers.
+unsafe impl Send for TraceState {}
+
+/// The recording of the events, the records are queued under the profiler lock, in the
+/// order of the changes of its tables, and written by a background thread.
+pub(crate) struct Tracer {
+    /// whether a recorder exists, from `record_trace` until it stops.
+    recording: AtomicBool,
+    active: AtomicBool,
+    state: Mutex<TraceState>,
+    wake: Condvar,
+}
+
+pub(crate) static TRACER: Tracer = Tracer {
+    recording: AtomicBool::new(false),
+    active: AtomicBool::new(false),
+    state: Mutex::new(TraceState {
+        records: Vec::new(),
+        stacks: None,
+        stop: false,
+    }),
+    wake: Condvar::new(),
+};
+
+static NEXT_THREAD: AtomicU32 = AtomicU32::new(1);
+
+fn thread_id() -> u32 {
+    let id = TRACE_THREAD.get();
+    if id != 0 {
+        return id;
+    }
+    let id = NEXT_THREAD.fetch_add(1, Ordering::Relaxed);
+    TRACE_THREAD.set(id);
+    id
+}
+
+impl Tracer {
+    #[inline(always)]
+    pub(crate) fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, TraceState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn push(&self, state: &mut TraceState, record: TraceRecord) {
+        state.records.push(record);
+        if state.records.len() >= WAKE_EVENTS {
+            self.wake.notify_one();
+        }
+    }
+
+    /// a block tracked by `HeapProfiler::insert`, called under the profiler lock.
+    pub(crate) fn alloc(
+        &self,
+        time: u64,
+        ptr: *const u8,
+        size: usize,
+        stack: StackId,
+        frames: &[*mut c_void],
+    ) {
+        let mut state = self.state();
+        state.push_stack(stack, frames);
+        let thread = thread_id();
+        let ptr = ptr as usize;
+        let record = match REALLOC_FROM.get() {
+            Some((old_ptr, true)) => {
+                REALLOC_FROM.set(Some((old_ptr, false)));
+                TraceRecord::Realloc {
+                    time,
+                    thread,
+                    old_ptr,
+                    ptr,
+                    size,
+                    stack,
+                }
+            }
+            _ => TraceRecord::Alloc {
+                time,
+                thread,
+                ptr,
+                size,
+                stack,
+            },
+        };
+        self.push(&mut state, record);
+    }
+
+    /// a block untracked by `HeapProfiler::remove`, called under the profiler lock.
+    pub(crate) fn free(&self, time: u64, ptr: *const u8) {
+        let ptr = ptr as usize;
+        // the event waits for the new block of the reallocation.
+        if let Some((old_ptr, false)) = REALLOC_FROM.get()
+            && old_ptr == ptr
+        {
+            REALLOC_FROM.set(Some((old_ptr, true)));
+            return;
+        }
+        let thread = thread_id();
+        self.push(&mut self.state(), TraceRecord::Free { time, thread, ptr });
+    }
+}
+
+/// A reallocation of the thread while recording, its untrack and track make one event.
+///
+/// `ProfAlloc::realloc` holds the profiler lock from the untrack to the track, no event of
+/// another thread comes in between.
+pub(crate) struct TraceRealloc(());
+
+impl TraceRealloc {
+    /// `None` unless recording, or inside the profiler.
+    #[inline(always)]
+    pub(crate) fn new(ptr: *const u8) -> Option<Self> {
+        if !TRACER.is_active() || !AllocEntry::new().top_entry() {
+            return None;
+        }
+        REALLOC_FROM.set(Some((ptr as usize, false)));
+        Some(Self(()))
+    }
+}
+
+impl Drop for TraceRealloc {
+    fn drop(&mut self) {
+        let _alloc_entry = AllocEntry::new();
+        // the new block was not tracked, the old one was only freed.
+        if let Some((ptr, true)) = REALLOC_FROM.take()
+            && TRACER.is_active()
+        {
+            let time = get_profiler().now();
+            let thread = thread_id();
+            TRACER.push(&mut TRACER.state(), TraceRecord::Free { time, thread, ptr });
+        }
+    }
+}
+
+/// Writes the records, the strings and the stacks are written before their first use.
+struct TraceWriter<W: Write> {
+    out: W,
+    buf: Vec<u8>,
+    strings: HashMap<String, u64>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        Ok(Self {
+            out,
+            buf: Vec::new(),
+            strings: HashMap::new(),
+        })
+    }
+
+    fn string(&mut self, s: &str) -> u64 {
+        if let Some(id) = self.strings.get(s) {
+            return *id;
+        }
+        let id = self.strings.len() as u64;
+        self.strings.insert(s.to_string(), id);
+        self.buf.push(TAG_STRING);
+        write_varint(&mut self.buf, id);
+        write_varint(&mut self.buf, s.len() as u64);
+        self.buf.extend_from_slice(s.as_bytes());
+        id
+    }
+
+    fn write(&mut self, profiler: &HeapProfiler, records: Vec<TraceRecord>) -> io::Result<()> {
+        for record in records {
+            match record {
+                TraceRecord::Stack { id, frames } => {
+                    let frames: Vec<_> = profiler
+                        .resolve_frames(&frames)
+                        .iter()
+                        .map(|symbol| {
+                            let frame = Frame::from(symbol);
+                            let function = self.string(&frame.function);
+                            let file = self.string(&frame.file);
+                            [function, file, frame.line as u64, frame.address as u64]
+                        })
+                        .collect();
+                    self.buf.push(TAG_STACK);
+                    write_varint(&mut self.buf, id as u64);
+                    write_varint(&mut self.buf, frames.len() as u64);
+                    for value in frames.iter().flatten() {
+                        write_varint(&mut self.buf, *value);
+                    }
+                }
+                TraceRecord::Alloc {
+                    time,
+                    thread,
+                    ptr,
+                    size,
+                    stack,
+                } => self.fields(
+                    TAG_ALLOC,
+                    &[time, thread as u64, ptr as u64, size as u64, stack as u64],
+                ),
+                TraceRecord::Free { time, thread, ptr } => {
+                    self.fields(TAG_FREE, &[time, thread as u64, ptr as u64])
+                }
+                TraceRecord::Realloc {
+                    time,
+                    thread,
+                    old_ptr,
+                    ptr,
+                    size,
+                    stack,
+                } => self.fields(
+                    TAG_REALLOC,
+                    &[
+                        time,
+                        thread as u64,
+                        old_ptr as u64,
+                        ptr as u64,
+                        size as u64,
+                        stack as u64,
+                    ],
+                ),
+            }
+        }
+        self.out.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    fn fields(&mut self, tag: u8, fields: &[u64]) {
+        self.buf.push(tag);
+        for field in fields {
+            write_varint(&mut self.buf, *field);
+        }
+    }
+}
+
+/// The loop of the writer thread, until the recorder stops or a write fails.
+fn write_trace(file: File) -> io::Result<()> {
+    let profiler = get_profiler();
+    let result = (|| {
+        let mut writer = TraceWriter::new(BufWriter::new(file))?;
+        loop {
+            let (records, stop) = {
+                let mut state = TRACER.state();
+                if state.records.is_empty() && !state.stop {
+                    state = TRACER
+                        .wake
+                        .wait_timeout(state, WRITE_PERIOD)
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .0;
+                }
+                (mem::take(&mut state.records), state.stop)
+            };
+            writer.write(profiler, records)?;
+            if stop {
+                return writer.out.flush();
+            }
+        }
+    })();
+    if result.is_err() {
+        // nothing would drain the records anymore.
+        TRACER.active.store(false, Ordering::Relaxed);
+    }
+    result
+}
+
+/// Record the tracked allocations, frees and reallocations to a binary log at `path`
+/// until the recorder stops, read it back with [`Trace::open`]. The blocks live at the
+/// start are recorded first, so the heap can be rebuilt at any time of the recording.
+///
+/// ```no_run
+/// use prof_mem::{ProfAlloc, ProfAllocConfig, Trace, record_trace};
+///
+/// #[global_allocator]
+/// static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+///
+/// let recorder = record_trace("heap.trace")?;
+/// // ...
+/// recorder.stop()?;
+/// let trace = Trace::open("heap.trace")?;
+/// let end = trace.events.last().map(|event| event.time()).unwrap_or_default();
+/// println!("{} live blocks", trace.live_at(end).len());
+/// # Ok::<(), std::io::Error>(())
+/// ```
+pub fn record_trace(path: impl AsRef<Path>) -> io::Result<TraceRecorder> {
+    let _alloc_entry = AllocEntry::new();
+    // claimed before the file is touched, it may be the one being recorded.
+    if TRACER.recording.swap(true, Ordering::Acquire) {
+        return Err(io::Error::other("a trace is already recording"));
+    }
+    match start_trace(path.as_ref()) {
+        Ok(writer) => Ok(TraceRecorder {
+            writer: Some(writer),
+        }),
+        Err(err) => {
+            TRACER.recording.store(false, Ordering::Release);
+            Err(err)
+        }
+    }
+}
+
+fn start_trace(path: &Path) -> io::Result<JoinHandle<io::Result<()>>> {
+    let profiler = get_profiler();
+    let file = File::create(path)?;
+    {
+        let _guard = profiler.lock();
+        let mut state = TRACER.state();
+        state.records.clear();
+        state.stacks = None;
+        state.stop = false;
+        profiler.for_each_block(|ptr, size, stack, time, frames| {
+            state.push_stack(stack, frames);
+            let ptr = ptr as usize;
+            let thread = 0;
+            state.records.push(TraceRecord::Alloc {
+                time,
+                thread,
+                ptr,
+                size,
+                stack,
+            });
+        });
+        TRACER.active.store(true, Ordering::Relaxed);
+    }
+    thread::Builder::new()
+        .name("prof-mem-trace".into())
+        .spawn(move || {
+            let _alloc_entry = AllocEntry::new();
+            write_trace(file)
+        })
+        .inspect_err(|_| TRACER.active.store(false, Ordering::Relaxed))
+}
+
+/// The recording of [`record_trace`], stopped on drop.
+#[must_use = "the recording stops when the recorder drops"]
+pub struct TraceRecorder {
+    writer: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl TraceRecorder {
+    /// Stop the recording and wait for the log to be written.
+    pub fn stop(mut self) -> io::Result<()> {
+        self.finish()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        let Some(writer) = self.writer.take() else {
+            return Ok(());
+        };
+        let _alloc_entry = AllocEntry::new();
+        {
+            // no event is being queued under the lock.
+            let _guard = get_profiler().lock();
+            TRACER.active.store(false, Ordering::Relaxed);
+            TRACER.state().stop = true;
+            TRACER.wake.notify_one();
+        }
+        let result = writer
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("the trace writer panicked")));
+        TRACER.recording.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl Drop for TraceRecorder {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
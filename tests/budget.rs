@@ -0,0 +1,89 @@
+use std::{
+    hint::black_box,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use prof_mem::{
+    Budget, BudgetViolation, ProfAlloc, ProfAllocConfig, budget_usage, budget_violations,
+    set_budget_callback, set_budgets,
+};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().min_size(1 << 10));
+
+const MIB: usize = 1 << 20;
+
+static CALLBACKS: AtomicUsize = AtomicUsize::new(0);
+
+fn on_violation(violation: &BudgetViolation) {
+    assert_eq!(violation.function, "budget_cache_fill");
+    // the profiler lock is released, the callback may look at the budgets.
+    let counted = CALLBACKS.load(Ordering::Relaxed) as u64 + 1;
+    assert_eq!(budget_usage()[0].violations, counted);
+    CALLBACKS.fetch_add(1, Ordering::Relaxed);
+}
+
+#[inline(never)]
+fn budget_cache_fill(cache: &mut Vec<Vec<u8>>, n: usize) {
+    for _ in 0..n {
+        cache.push(black_box(vec![1u8; MIB]));
+    }
+}
+
+#[test]
+fn test_budgets() {
+    let mut cache = Vec::with_capacity(16);
+    // the blocks live when the budgets are set are counted.
+    budget_cache_fill(&mut cache, 1);
+    set_budgets([
+        Budget::new("budget_cache_fill", 4 * MIB as u64),
+        Budget::new("no_such_function", 0),
+    ]);
+    set_budget_callback(Some(on_violation));
+    let usage = budget_usage();
+    assert_eq!(usage[0].live_bytes, MIB as u64);
+    assert_eq!(usage[1].live_bytes, 0);
+
+    budget_cache_fill(&mut cache, 2);
+    let other = black_box(vec![2u8; 8 * MIB]);
+    assert_eq!(budget_usage()[0].live_bytes, 3 * MIB as u64);
+    assert!(budget_violations().is_empty());
+
+    // crossing the limit is reported once.
+    budget_cache_fill(&mut cache, 3);
+    assert_eq!(CALLBACKS.load(Ordering::Relaxed), 1);
+    let violations = budget_violations();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].budget, 0);
+    assert_eq!(violations[0].live_bytes, 5 * MIB as u64);
+    assert!(
+        violations[0]
+            .frames
+            .iter()
+            .any(|frame| frame.function.contains("budget_cache_fill")),
+        "{}",
+        violations[0]
+    );
+
+    // back under the limit, the next crossing is reported again.
+    cache.truncate(2);
+    assert_eq!(budget_usage()[0].live_bytes, 2 * MIB as u64);
+    budget_cache_fill(&mut cache, 4);
+    assert_eq!(CALLBACKS.load(Ordering::Relaxed), 2);
+    let usage = budget_usage();
+    assert_eq!(usage[0].peak_bytes, 6 * MIB as u64);
+    assert_eq!(usage[0].violations, 2);
+    assert_eq!(usage[1].violations, 0);
+
+    // a panic of the callback does not unwind out of the allocator.
+    set_budget_callback(Some(|_| panic!("over budget")));
+    cache.truncate(2);
+    budget_cache_fill(&mut cache, 4);
+    assert_eq!(budget_usage()[0].violations, 3);
+
+    drop((cache, other));
+    assert_eq!(budget_usage()[0].live_bytes, 0);
+    set_budget_callback(None);
+    set_budgets([]);
+    assert!(budget_usage().is_empty());
+}
@@ -0,0 +1,118 @@
+#![cfg(feature = "cli")]
+
+use std::{
+    fs,
+    hint::black_box,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use prof_mem::{DumpOptions, HeapProfile, ProfAlloc, ProfAllocConfig, dump_with};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn cli_site() -> Vec<u8> {
+    black_box(vec![1u8; 1 << 20])
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("prof-mem-cli-{}-{name}", std::process::id()))
+}
+
+fn prof_mem(args: &[&str]) -> String {
+    let output = Command::new(env!("CARGO_BIN_EXE_prof-mem"))
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+fn path_str(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+#[test]
+fn test_cli() {
+    let base = temp_path("base.pb");
+    dump_with(&DumpOptions::new().path(&base)).unwrap();
+    let block = cli_site();
+    let new = temp_path("new.pb");
+    dump_with(&DumpOptions::new().path(&new)).unwrap();
+    let (base, new) = (path_str(&base), path_str(&new));
+
+    let top = prof_mem(&["top", "-n", "5", new]);
+    let first = top.lines().nth(1).unwrap();
+    assert!(first.contains("cli_site"), "{top}");
+    assert!(first.trim_start().starts_with("1048576"), "{top}");
+
+    let tree = prof_mem(&["tree", "--min-percent", "50", new]);
+    assert!(
+        tree.lines().any(|line| line.ends_with("cli::cli_site")),
+        "{tree}"
+    );
+
+    let diff = prof_mem(&["diff", base, new]);
+    assert!(diff.lines().nth(1).unwrap().contains("+1048576"), "{diff}");
+
+    // the fresh block is not old enough for a leak.
+    let leaks = prof_mem(&["leaks", "--min-age", "3600000", new]);
+    assert!(!leaks.contains("cli_site"), "{leaks}");
+    let leaks = prof_mem(&["leaks", "--min-age", "0", new]);
+    assert!(leaks.contains("cli_site"), "{leaks}");
+
+    let svg = temp_path("new.svg");
+    prof_mem(&["flamegraph", "-o", path_str(&svg), new]);
+    assert!(fs::read_to_string(&svg).unwrap().contains("cli_site"));
+
+    let merged = temp_path("merged.pb");
+    prof_mem(&["merge", "--source", "-o", path_str(&merged), base, new]);
+    let merged_profile = HeapProfile::open(&merged).unwrap();
+    let (base_profile, new_profile) = (
+        HeapProfile::open(base).unwrap(),
+        HeapProfile::open(new).unwrap(),
+    );
+    assert_eq!(
+        merged_profile.samples.len(),
+        base_profile.samples.len() + new_profile.samples.len()
+    );
+    assert_eq!(
+        merged_profile.total(0),
+        base_profile.total(0) + new_profile.total(0)
+    );
+    let new_name = Path::new(new).file_name().unwrap().to_str().unwrap();
+    assert!(
+        merged_profile
+            .samples
+            .iter()
+            .any(|s| s.str_label("source") == Some(new_name))
+    );
+
+    drop(block);
+    for path in [base.as_ref(), new.as_ref(), svg.as_path(), merged.as_path()] {
+        let _ = fs::remove_file(path);
+    }
+}
+
+#[test]
+fn test_cli_errors() {
+    let output = Command::new(env!("CARGO_BIN_EXE_prof-mem"))
+        .args(["top", "/nonexistent/mem.pb"])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("/nonexistent/mem.pb"));
+
+    let output = Command::new(env!("CARGO_BIN_EXE_prof-mem"))
+        .arg("frobnicate")
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("usage: prof-mem"));
+}
@@ -0,0 +1,50 @@
+use std::collections::HashSet;
+
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig, dump_to, is_enabled, set_enabled};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(
+    ProfAllocConfig::new()
+        .max_depth(32)
+        .enabled(false)
+        .size_range(64, 4096),
+);
+
+#[test]
+fn test_config() {
+    let before = Box::new([0u8; 128]);
+    assert!(!is_enabled());
+
+    set_enabled(true);
+    let large = Box::new([0u8; 128]);
+    let small = Box::new([0u8; 16]);
+    let huge = vec![0u8; 8192];
+    set_enabled(false);
+
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    let profile = HeapProfile::parse(&buf).unwrap();
+    let tracked: HashSet<_> = profile
+        .samples
+        .iter()
+        .filter_map(|s| s.str_label("alloc"))
+        .collect();
+    let is_tracked = |ptr: *const u8| tracked.contains(format!("{ptr:p}").as_str());
+    assert!(!is_tracked(before.as_ptr()));
+    assert!(is_tracked(large.as_ptr()));
+    assert!(!is_tracked(small.as_ptr()));
+    assert!(!is_tracked(huge.as_ptr()));
+    let comments: Vec<_> = profile.comments.iter().map(String::as_str).collect();
+    assert!(comments.contains(&"size filter: 64..=4096 bytes"));
+    assert!(comments.contains(&"max depth: 32, sample rate: 1"));
+    assert!(comments.contains(&format!("pid: {}", std::process::id()).as_str()));
+    assert!(comments.iter().any(|c| c.starts_with("prof-mem ")));
+    assert!(profile.time_nanos > 0);
+    assert!(profile.duration_nanos > 0);
+    // the inlined functions share the address of their frame.
+    for sample in &profile.samples {
+        let mut addrs: Vec<_> = sample.frames.iter().map(|f| f.address).collect();
+        addrs.dedup();
+        assert!(addrs.len() <= 32);
+    }
+}
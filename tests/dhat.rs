@@ -0,0 +1,45 @@
+use std::hint::black_box;
+
+use prof_mem::{DumpFormat, DumpOptions, ProfAlloc, ProfAllocConfig, dump_to_with};
+use serde_json::Value;
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn dhat_site() -> Vec<u8> {
+    black_box(vec![1u8; 1000])
+}
+
+#[test]
+fn test_dhat() {
+    let mut blocks: Vec<_> = (0..10).map(|_| dhat_site()).collect();
+    blocks.truncate(4);
+
+    let mut buf = Vec::new();
+    dump_to_with(&mut buf, &DumpOptions::new().format(DumpFormat::Dhat)).unwrap();
+    let dhat: Value = serde_json::from_slice(&buf).unwrap();
+    assert_eq!(dhat["dhatFileVersion"], 2);
+    assert_eq!(dhat["mode"], "rust-heap");
+    assert_eq!(dhat["pid"], std::process::id());
+
+    let ftbl = dhat["ftbl"].as_array().unwrap();
+    assert_eq!(ftbl[0], "[root]");
+    let pp = dhat["pps"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|pp| {
+            let top = pp["fs"][0].as_u64().unwrap() as usize;
+            ftbl[top].as_str().unwrap().contains("dhat_site")
+        })
+        .expect("the site should be listed");
+    assert_eq!(pp["tbk"], 10);
+    assert_eq!(pp["tb"], 10_000);
+    assert_eq!(pp["mbk"], 10);
+    assert_eq!(pp["mb"], 10_000);
+    assert_eq!(pp["ebk"], 4);
+    assert_eq!(pp["eb"], 4_000);
+    assert!(pp["gbk"].as_u64().unwrap() <= 10);
+    drop(blocks);
+}
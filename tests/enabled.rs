@@ -0,0 +1,21 @@
+use std::alloc::{GlobalAlloc, Layout};
+
+use prof_mem::{ProfAlloc, ProfAllocConfig, is_enabled, set_enabled};
+
+// not the global allocator, the profiler starts at the first allocation of the test.
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().enabled(true));
+
+#[test]
+fn test_set_enabled_before_start() {
+    set_enabled(false);
+    let layout = Layout::new::<[u8; 64]>();
+    unsafe {
+        let ptr = ALLOC.alloc(layout);
+        assert!(!ptr.is_null());
+        // the explicit call wins over the config.
+        assert!(!is_enabled());
+        ALLOC.dealloc(ptr, layout);
+    }
+    set_enabled(true);
+    assert!(is_enabled());
+}
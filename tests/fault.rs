@@ -0,0 +1,74 @@
+use std::{collections::TryReserveError, hint::black_box};
+
+use prof_mem::{
+    FaultRule, ProfAlloc, ProfAllocConfig, clear_faults, inject_faults, injected_faults,
+};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().enabled(false));
+
+const MIB: usize = 1 << 20;
+
+fn try_alloc(size: usize) -> Result<Vec<u8>, TryReserveError> {
+    let mut v = Vec::new();
+    v.try_reserve_exact(size)?;
+    Ok(black_box(v))
+}
+
+#[inline(never)]
+fn faulty_site(size: usize) -> Result<Vec<u8>, TryReserveError> {
+    black_box(try_alloc(size))
+}
+
+fn failures(n: usize, f: impl Fn() -> Result<Vec<u8>, TryReserveError>) -> usize {
+    (0..n).filter(|_| f().is_err()).count()
+}
+
+#[test]
+fn test_fault_injection() {
+    // every 3rd allocation of 1 MiB or more, the smaller ones are left alone.
+    inject_faults([FaultRule::new().min_size(MIB).every(3)]);
+    assert_eq!(failures(9, || try_alloc(MIB)), 3);
+    assert_eq!(failures(9, || try_alloc(1024)), 0);
+    let faults = injected_faults();
+    assert_eq!(faults.len(), 3);
+    assert!(
+        faults
+            .iter()
+            .all(|fault| fault.size == MIB && fault.rule == 0)
+    );
+
+    // by probability.
+    inject_faults([FaultRule::new().min_size(MIB).probability(0.5)]);
+    let failed = failures(200, || try_alloc(MIB));
+    assert!((50..150).contains(&failed), "{failed}");
+    inject_faults([FaultRule::new().min_size(MIB).probability(0.0)]);
+    assert_eq!(failures(20, || try_alloc(MIB)), 0);
+    injected_faults();
+
+    // only under the function, the log has the stacks.
+    inject_faults([FaultRule::new().min_size(MIB).in_function("faulty_site")]);
+    assert_eq!(failures(5, || try_alloc(MIB)), 0);
+    assert_eq!(failures(5, || faulty_site(MIB)), 5);
+    let faults = injected_faults();
+    assert_eq!(faults.len(), 5);
+    assert!(
+        faults[0]
+            .frames
+            .iter()
+            .any(|frame| frame.function.contains("faulty_site")),
+        "{}",
+        faults[0]
+    );
+
+    // the reallocations fail too, the block is left untouched.
+    inject_faults([FaultRule::new().min_size(4 * MIB)]);
+    let mut v = vec![7u8; MIB];
+    assert!(v.try_reserve_exact(4 * MIB).is_err());
+    assert_eq!((v.len(), v[MIB - 1]), (MIB, 7));
+
+    clear_faults();
+    assert!(v.try_reserve_exact(4 * MIB).is_ok());
+    assert_eq!(failures(5, || faulty_site(MIB)), 0);
+    assert_eq!(injected_faults().len(), 1);
+}
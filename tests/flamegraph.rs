@@ -0,0 +1,44 @@
+#![cfg(feature = "flamegraph")]
+
+use std::hint::black_box;
+
+use prof_mem::{FlamegraphMetric, ProfAlloc, ProfAllocConfig, dump_flamegraph, write_flamegraph};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn flame_site() -> Vec<u8> {
+    black_box(vec![1u8; 1 << 20])
+}
+
+#[test]
+fn test_flamegraph() {
+    let block = flame_site();
+    let mut buf = Vec::new();
+    write_flamegraph(&mut buf, FlamegraphMetric::InuseBytes).unwrap();
+    let svg = String::from_utf8(buf).unwrap();
+    assert!(svg.starts_with("<?xml"));
+    assert!(svg.contains("In-use bytes"));
+    assert!(svg.contains("flamegraph::flame_site"));
+    assert!(svg.contains("function search()"));
+    drop(block);
+
+    let mut buf = Vec::new();
+    write_flamegraph(&mut buf, FlamegraphMetric::Allocations).unwrap();
+    // freed blocks still count as allocations.
+    assert!(
+        String::from_utf8(buf)
+            .unwrap()
+            .contains("flamegraph::flame_site")
+    );
+
+    let path = std::env::temp_dir().join(format!("prof-mem-{}.svg", std::process::id()));
+    dump_flamegraph(&path).unwrap();
+    assert!(
+        std::fs::read_to_string(&path)
+            .unwrap()
+            .ends_with("</svg>\n")
+    );
+    std::fs::remove_file(path).unwrap();
+}
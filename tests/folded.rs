@@ -0,0 +1,43 @@
+use std::hint::black_box;
+
+use prof_mem::{DumpFormat, DumpOptions, ProfAlloc, ProfAllocConfig, dump_to_with, dump_with};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn folded_site(blocks: &mut Vec<Vec<u8>>) {
+    for _ in 0..black_box(10) {
+        blocks.push(black_box(vec![1u8; 1000]));
+    }
+}
+
+#[test]
+fn test_folded() {
+    let mut blocks = Vec::with_capacity(10);
+    folded_site(&mut blocks);
+    let options = DumpOptions::new().format(DumpFormat::Folded);
+    let mut buf = Vec::new();
+    dump_to_with(&mut buf, &options).unwrap();
+    let folded = String::from_utf8(buf).unwrap();
+
+    // the 10 blocks share one stack, root first and the site last.
+    let (_, bytes) = folded
+        .lines()
+        .filter_map(|line| line.rsplit_once(' '))
+        .find(|(stack, _)| {
+            stack
+                .rsplit(';')
+                .next()
+                .is_some_and(|leaf| leaf.contains("folded_site"))
+        })
+        .expect("the site should be folded");
+    assert_eq!(bytes.parse::<u64>().unwrap(), 10_000);
+    drop(blocks);
+
+    let path = std::env::temp_dir().join(format!("prof-mem-{}.folded", std::process::id()));
+    let written = dump_with(&options.path(&path)).unwrap();
+    assert_eq!(written, path);
+    assert!(std::fs::metadata(&path).unwrap().len() > 0);
+    std::fs::remove_file(path).unwrap();
+}
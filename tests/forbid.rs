@@ -0,0 +1,51 @@
+use std::{hint::black_box, panic};
+
+use prof_mem::{ForbidAction, ProfAlloc, ProfAllocConfig, forbid_alloc, forbid_alloc_with};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn allocating_path() -> Vec<u8> {
+    black_box(vec![0u8; 123])
+}
+
+#[test]
+fn test_forbid_alloc() {
+    // a path that does not allocate passes.
+    let mut buf = Vec::<u64>::with_capacity(16);
+    {
+        let _guard = forbid_alloc();
+        buf.extend(black_box(0..16u64));
+    }
+    assert_eq!(buf.iter().sum::<u64>(), 120);
+
+    // the caught allocations come with their stacks.
+    let guard = forbid_alloc_with(ForbidAction::Report);
+    let v = allocating_path();
+    let allocs = guard.finish();
+    drop(v);
+    assert_eq!(allocs.len(), 1);
+    assert_eq!(allocs[0].size, 123);
+    assert!(
+        allocs[0]
+            .frames
+            .iter()
+            .any(|frame| frame.function.contains("allocating_path")),
+        "{}",
+        allocs[0]
+    );
+
+    // the panic happens when the guard drops, outside the allocator.
+    let result = panic::catch_unwind(|| {
+        let _guard = forbid_alloc();
+        drop(allocating_path());
+    });
+    let message = result.unwrap_err();
+    let message = message.downcast_ref::<String>().unwrap();
+    assert!(message.contains("1 forbidden allocation(s)"), "{message}");
+    assert!(message.contains("allocation of 123 bytes"), "{message}");
+
+    // the allocations after the guard drops are allowed again.
+    drop(allocating_path());
+}
@@ -0,0 +1,52 @@
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig, dump_to};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn user_alloc_site() -> Box<[u8; 1024]> {
+    Box::new([1u8; 1024])
+}
+
+#[inline(never)]
+fn user_zeroed_site() -> Vec<u8> {
+    vec![0u8; 2048]
+}
+
+#[inline(never)]
+fn user_realloc_site(v: &mut Vec<u64>) {
+    v.reserve(4096);
+    // keep the frame, a tail call into `reserve` would leave no trace of it.
+    std::hint::black_box(v);
+}
+
+/// the name of the top function of the sample allocated at `ptr`.
+fn top_function(profile: &HeapProfile, ptr: *const u8) -> &str {
+    let ptr = format!("{ptr:p}");
+    let sample = profile
+        .samples
+        .iter()
+        .find(|s| s.str_label("alloc") == Some(ptr.as_str()))
+        .expect("the allocation should be tracked");
+    &sample.frames[0].function
+}
+
+#[test]
+fn test_top_frame_is_caller() {
+    let boxed = user_alloc_site();
+    let zeroed = user_zeroed_site();
+    let mut grown = Vec::<u64>::with_capacity(1);
+    user_realloc_site(&mut grown);
+
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    let profile = HeapProfile::parse(&buf).unwrap();
+    for (ptr, site) in [
+        (boxed.as_ptr(), "user_alloc_site"),
+        (zeroed.as_ptr(), "user_zeroed_site"),
+        (grown.as_ptr() as *const u8, "user_realloc_site"),
+    ] {
+        let top = top_function(&profile, ptr);
+        assert!(top.contains(site), "top frame is {top}");
+    }
+}
@@ -0,0 +1,43 @@
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig, dump_to, size_histogram};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().size_labels(true));
+
+#[test]
+fn test_size_histogram() {
+    let before = size_histogram();
+    let class = |h: &prof_mem::SizeHistogram| {
+        h.class_of(1000)
+            .map(|c| (c.live_blocks, c.total_blocks))
+            .unwrap_or_default()
+    };
+    let (live, total) = class(&before);
+
+    let blocks: Vec<_> = (0..100).map(|_| Box::new([0u8; 1000])).collect();
+    let during = size_histogram();
+    let c = during.class_of(1000).unwrap();
+    assert_eq!((c.min_size, c.max_size), (512, 1023));
+    assert!(c.live_blocks >= live + 100);
+    assert!(c.total_blocks >= total + 100);
+    assert!(during.to_string().contains("512..=1023"));
+
+    {
+        // the `bytes` label of the first block.
+        let mut buf = Vec::new();
+        dump_to(&mut buf).unwrap();
+        let profile = HeapProfile::parse(&buf).unwrap();
+        let ptr = format!("{:p}", blocks[0].as_ptr());
+        let sample = profile
+            .samples
+            .iter()
+            .find(|s| s.str_label("alloc") == Some(ptr.as_str()))
+            .unwrap();
+        assert_eq!(sample.num_label("bytes"), Some(1000));
+    }
+
+    drop(blocks);
+    let after = size_histogram();
+    let (live_after, total_after) = class(&after);
+    assert!(live_after + 100 <= class(&during).0);
+    assert!(total_after >= total + 100);
+}
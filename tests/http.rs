@@ -0,0 +1,109 @@
+#![cfg(feature = "http")]
+
+use std::{
+    hint::black_box,
+    io::{Read, Write},
+    net::{SocketAddr, TcpStream},
+    thread,
+    time::Duration,
+};
+
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig, serve_http};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn http_site() -> Vec<u8> {
+    black_box(vec![1u8; 1 << 16])
+}
+
+/// the status line and the body of a GET of `target`.
+fn get(addr: SocketAddr, target: &str) -> (String, Vec<u8>) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "GET {target} HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let mut response = Vec::new();
+    stream.read_to_end(&mut response).unwrap();
+    let end = response.windows(4).position(|w| w == b"\r\n\r\n").unwrap();
+    let head = String::from_utf8(response[..end].to_vec()).unwrap();
+    let status = head.lines().next().unwrap().to_string();
+    (status, response[end + 4..].to_vec())
+}
+
+fn site_value(profile: &HeapProfile) -> i64 {
+    profile
+        .samples
+        .iter()
+        .filter(|s| s.frames[0].function.contains("http_site"))
+        .map(|s| s.values[0])
+        .sum()
+}
+
+#[test]
+fn test_heap_endpoint() {
+    let server = serve_http("127.0.0.1:0").unwrap();
+    let addr = server.local_addr();
+    let block = http_site();
+
+    let (status, body) = get(addr, "/debug/pprof/heap");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    // the parsed profiles are dropped right away, their blocks would weigh on the next dumps.
+    assert_eq!(site_value(&HeapProfile::parse(&body).unwrap()), 1 << 16);
+
+    let (_, body) = get(addr, "/debug/pprof/heap?gc=1&sample_index=inuse_objects");
+    let (kind, value) = {
+        let profile = HeapProfile::parse(&body).unwrap();
+        (profile.sample_types[0].kind.clone(), site_value(&profile))
+    };
+    assert_eq!((kind.as_str(), value), ("objects", 1));
+
+    let (_, body) = get(addr, "/debug/pprof/heap?debug=1");
+    let report = String::from_utf8(body).unwrap();
+    assert!(report.contains("http_site"), "{report}");
+
+    // the block allocated during the window shows up, the one before does not.
+    let delta = thread::spawn(move || get(addr, "/debug/pprof/heap?seconds=1"));
+    thread::sleep(Duration::from_millis(300));
+    let second = http_site();
+    let (status, body) = delta.join().unwrap();
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    let profile = HeapProfile::parse(&body).unwrap();
+    assert!(profile.samples.iter().all(|s| s.labels.is_empty()));
+    assert_eq!(site_value(&profile), 1 << 16);
+    drop(profile);
+
+    let (status, body) = get(addr, "/metrics");
+    assert_eq!(status, "HTTP/1.1 200 OK");
+    assert!(
+        String::from_utf8(body)
+            .unwrap()
+            .contains("prof_mem_inuse_bytes ")
+    );
+
+    assert_eq!(get(addr, "/nothing").0, "HTTP/1.1 404 Not Found");
+    assert_eq!(
+        get(addr, "/debug/pprof/heap?sample_index=bogus").0,
+        "HTTP/1.1 400 Bad Request"
+    );
+    drop((block, second));
+
+    // one delta too many is turned away, a worker is left for the other requests.
+    let deltas: Vec<_> = (0..4)
+        .map(|_| thread::spawn(move || get(addr, "/debug/pprof/heap?seconds=1").0))
+        .collect();
+    thread::sleep(Duration::from_millis(300));
+    assert_eq!(get(addr, "/metrics").0, "HTTP/1.1 200 OK");
+    let busy = deltas
+        .into_iter()
+        .map(|delta| delta.join().unwrap())
+        .filter(|status| status == "HTTP/1.1 503 Service Unavailable")
+        .count();
+    assert_eq!(busy, 1);
+
+    server.stop();
+    let closed = (0..100).any(|_| {
+        thread::sleep(Duration::from_millis(10));
+        TcpStream::connect(addr).is_err()
+    });
+    assert!(closed, "the server still accepts connections");
+}
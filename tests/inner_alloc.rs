@@ -0,0 +1,57 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use prof_mem::{ProfAlloc, ProfAllocConfig};
+
+/// counts the calls reaching the inner allocator.
+struct Counting {
+    allocs: AtomicUsize,
+    reallocs: AtomicUsize,
+    deallocs: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.reallocs.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.deallocs.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: ProfAlloc<Counting> = ProfAlloc::with_allocator(
+    Counting {
+        allocs: AtomicUsize::new(0),
+        reallocs: AtomicUsize::new(0),
+        deallocs: AtomicUsize::new(0),
+    },
+    ProfAllocConfig::new().max_depth(64),
+);
+
+#[test]
+fn test_forward_to_inner() {
+    let inner = ALLOC.inner();
+    let allocs = inner.allocs.load(Ordering::Relaxed);
+    let reallocs = inner.reallocs.load(Ordering::Relaxed);
+    let deallocs = inner.deallocs.load(Ordering::Relaxed);
+
+    let mut v = Vec::<u8>::with_capacity(16);
+    v.reserve(4096);
+    drop(v);
+
+    assert!(inner.allocs.load(Ordering::Relaxed) > allocs);
+    assert!(inner.reallocs.load(Ordering::Relaxed) > reallocs);
+    assert!(inner.deallocs.load(Ordering::Relaxed) > deallocs);
+    prof_mem::dump_to(std::io::sink()).unwrap();
+}
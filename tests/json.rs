@@ -0,0 +1,59 @@
+use std::hint::black_box;
+
+use prof_mem::{DumpFormat, DumpOptions, ProfAlloc, ProfAllocConfig, dump_to_with};
+use serde_json::Value;
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn json_site() -> Vec<u8> {
+    black_box(vec![1u8; 3000])
+}
+
+#[test]
+fn test_json_lines() {
+    // one call site, the last block is freed.
+    let mut kept = Vec::with_capacity(4);
+    for _ in 0..black_box(4) {
+        kept.push(json_site());
+    }
+    drop(kept.pop());
+
+    let mut buf = Vec::new();
+    dump_to_with(&mut buf, &DumpOptions::new().format(DumpFormat::Json)).unwrap();
+    let records: Vec<Value> = String::from_utf8(buf)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    let profile = &records[0];
+    assert_eq!(profile["type"], "profile");
+    assert_eq!(profile["schema"], 1);
+    assert_eq!(profile["pid"], std::process::id());
+    assert!(profile["comments"].as_array().unwrap().len() > 2);
+
+    let stack = records[1..]
+        .iter()
+        .find(|r| {
+            r["frames"][0]["function"]
+                .as_str()
+                .unwrap()
+                .contains("json_site")
+        })
+        .expect("the site should be exported");
+    assert_eq!(stack["type"], "stack");
+    assert_eq!(stack["inuse_blocks"], 3);
+    assert_eq!(stack["inuse_bytes"], 9000);
+    assert_eq!(stack["labels"]["bytes"], 3000);
+    assert!(stack["labels"]["age_ms"].is_u64());
+    let frame = &stack["frames"][0];
+    // the release profile has no debug info for the file and line.
+    if cfg!(debug_assertions) {
+        assert!(frame["file"].as_str().unwrap().ends_with("json.rs"));
+        assert!(frame["line"].as_u64().unwrap() > 0);
+    }
+    assert!(frame["address"].as_str().unwrap().starts_with("0x"));
+    drop(kept);
+}
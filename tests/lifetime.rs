@@ -0,0 +1,84 @@
+use std::{hint::black_box, thread, time::Duration};
+
+use prof_mem::{HeapProfile, LabelValue, ProfAlloc, ProfAllocConfig, dump_to, lifetimes};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn churn_site() {
+    for _ in 0..1000 {
+        black_box(Box::new([0u8; 64]));
+    }
+}
+
+#[inline(never)]
+fn keep_site() -> Box<[u8; 64]> {
+    black_box(Box::new([0u8; 64]))
+}
+
+// not the size of `keep_site`, the identical functions are merged in release builds.
+#[inline(never)]
+fn long_site() -> Box<[u8; 128]> {
+    black_box(Box::new([0u8; 128]))
+}
+
+fn is_site(site: &prof_mem::SiteLifetime, name: &str) -> bool {
+    site.frames
+        .first()
+        .is_some_and(|frame| frame.function.contains(name))
+}
+
+#[test]
+fn test_lifetimes() {
+    let kept = long_site();
+    churn_site();
+    thread::sleep(Duration::from_millis(50));
+
+    let report = lifetimes();
+    let churn = report
+        .short_lived(Duration::from_millis(10))
+        .into_iter()
+        .find(|s| is_site(s, "churn_site"))
+        .expect("the churn site should be short-lived");
+    assert!(churn.freed_blocks >= 1000);
+    assert_eq!(
+        churn.histogram.buckets.iter().sum::<u64>(),
+        churn.freed_blocks
+    );
+
+    let keep = report
+        .long_lived(Duration::from_millis(50))
+        .into_iter()
+        .find(|s| is_site(s, "long_site"))
+        .expect("the kept block should be long-lived");
+    assert_eq!(keep.live_blocks, 1);
+    assert!(report.to_string().contains("short-lived sites"));
+    drop(kept);
+}
+
+#[test]
+fn test_age_label() {
+    let old = keep_site();
+    thread::sleep(Duration::from_millis(50));
+    let young = keep_site();
+
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    let profile = HeapProfile::parse(&buf).unwrap();
+    let age = |ptr: *const u8| {
+        let ptr = format!("{ptr:p}");
+        let sample = profile
+            .samples
+            .iter()
+            .find(|s| s.str_label("alloc") == Some(ptr.as_str()))
+            .unwrap();
+        let Some(LabelValue::Num { value, unit }) = sample.label("age_ms") else {
+            panic!("the block should have an age");
+        };
+        assert_eq!(unit, "ms");
+        *value
+    };
+    assert!(age(old.as_ptr()) >= 50);
+    assert!(age(young.as_ptr()) < age(old.as_ptr()));
+}
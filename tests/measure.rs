@@ -0,0 +1,47 @@
+use std::{hint::black_box, thread};
+
+use prof_mem::{ProfAlloc, ProfAllocConfig, measure};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[test]
+fn test_measure() {
+    let (sum, m) = measure(|| black_box(0..1000u64).sum::<u64>());
+    assert_eq!(sum, 499500);
+    assert_eq!(m.allocations + m.reallocations + m.deallocations, 0);
+
+    // the stacks captured by the profiler are not counted.
+    let kept = Vec::<u8>::with_capacity(100);
+    let (v, m) = measure(|| {
+        drop(kept);
+        let mut v = black_box(Vec::<u8>::with_capacity(1000));
+        v.reserve(3000);
+        v
+    });
+    assert_eq!(m.allocations, 1);
+    assert_eq!(m.reallocations, 1);
+    assert_eq!(m.deallocations, 1);
+    assert_eq!(m.allocated_bytes, 1000 + v.capacity() as u64);
+    assert_eq!(m.freed_bytes, 100 + 1000);
+    assert_eq!(m.retained_bytes(), v.capacity() as i64 - 100);
+
+    // nested scopes count the calls of their own closure.
+    let (inner, outer) = measure(|| {
+        let first = black_box(vec![1u8; 10]);
+        let (second, inner) = measure(|| black_box(vec![2u8; 20]));
+        drop((first, second));
+        inner
+    });
+    assert_eq!((inner.allocations, inner.allocated_bytes), (1, 20));
+    assert_eq!((outer.allocations, outer.allocated_bytes), (2, 30));
+    assert_eq!(outer.retained_bytes(), 0);
+
+    // the other threads are not counted.
+    let (_, m) = measure(|| {
+        thread::scope(|s| {
+            s.spawn(|| black_box(vec![0u8; 1 << 20]));
+        })
+    });
+    assert!(m.allocated_bytes < 1 << 20);
+}
@@ -0,0 +1,108 @@
+use std::hint::black_box;
+
+use prof_mem::{
+    HeapProfile, ProfAlloc, ProfAllocConfig, ProfileMerger, SOURCE_LABEL, dump_to, merge_profiles,
+};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn merge_site() -> Vec<u8> {
+    black_box(vec![1u8; 4096])
+}
+
+fn dump() -> Vec<u8> {
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    buf
+}
+
+fn parse(data: &[u8]) -> HeapProfile {
+    HeapProfile::parse(data).unwrap()
+}
+
+#[test]
+fn test_merge_with_sources() {
+    let first = dump();
+    let block = merge_site();
+    let second = dump();
+
+    let mut merger = ProfileMerger::new();
+    merger.add(&first, Some("worker-1")).unwrap();
+    merger.add(&second, Some("worker-2")).unwrap();
+    assert_eq!(merger.len(), 2);
+    let mut merged = Vec::new();
+    merger.write_to(&mut merged).unwrap();
+
+    let (first, second, heap) = (parse(&first), parse(&second), parse(&merged));
+    assert_eq!(
+        heap.samples.len(),
+        first.samples.len() + second.samples.len()
+    );
+    assert_eq!(heap.total(0), first.total(0) + second.total(0));
+    assert!(heap.time_nanos <= second.time_nanos);
+
+    let ptr = format!("{:p}", block.as_ptr());
+    let sample = heap
+        .samples
+        .iter()
+        .find(|s| s.str_label("alloc") == Some(ptr.as_str()))
+        .unwrap();
+    assert_eq!(sample.str_label(SOURCE_LABEL), Some("worker-2"));
+    assert!(sample.frames[0].function.contains("merge_site"));
+    assert!(
+        heap.samples
+            .iter()
+            .all(|s| s.str_label(SOURCE_LABEL).is_some())
+    );
+
+    // merging the merged profile keeps the first sources.
+    let mut merger = ProfileMerger::new();
+    merger.add(&merged, Some("fleet")).unwrap();
+    let mut remerged = Vec::new();
+    merger.write_to(&mut remerged).unwrap();
+    assert!(
+        parse(&remerged)
+            .samples
+            .iter()
+            .all(|s| s.str_label(SOURCE_LABEL) != Some("fleet"))
+    );
+}
+
+#[test]
+fn test_merge_adds_up_samples() {
+    let data = dump();
+    let mut merged_data = Vec::new();
+    merge_profiles([&data[..], &data[..]], &mut merged_data).unwrap();
+    // the stacks shared by both dumps are written once.
+    assert!(merged_data.len() < 2 * data.len());
+    let (profile, merged) = (parse(&data), parse(&merged_data));
+    assert_eq!(merged.samples.len(), profile.samples.len());
+    assert_eq!(merged.total(0), profile.total(0) * 2);
+    assert_eq!(merged.comments, profile.comments);
+
+    // a profile of `objects/count`, the sample types differ.
+    let sample_type = [0x0a, 0x04, 0x08, 0x01, 0x10, 0x02];
+    let mut other = sample_type.to_vec();
+    for s in ["", "objects", "count"] {
+        other.extend([0x32, s.len() as u8]);
+        other.extend(s.as_bytes());
+    }
+    assert!(merge_profiles([&data[..], &other], std::io::sink()).is_err());
+
+    // a sample with two values for the one sample type.
+    let mut uneven = other.clone();
+    uneven.extend([0x12, 0x04, 0x12, 0x02, 0x01, 0x02]);
+    assert!(merge_profiles([&other[..]], std::io::sink()).is_ok());
+    assert!(merge_profiles([&uneven[..]], std::io::sink()).is_err());
+
+    // the rejected profile leaves nothing behind.
+    let mut merger = ProfileMerger::new();
+    merger.add(&data, None).unwrap();
+    assert!(merger.add(&other, None).is_err());
+    merger.add(&data, None).unwrap();
+    let mut again = Vec::new();
+    merger.write_to(&mut again).unwrap();
+    assert_eq!(again, merged_data);
+}
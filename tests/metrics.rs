@@ -0,0 +1,45 @@
+use std::{hint::black_box, time::Duration};
+
+use prof_mem::{ProfAlloc, ProfAllocConfig, heap_metrics, metrics};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn metrics_site() -> Vec<u8> {
+    black_box(vec![1u8; 1 << 20])
+}
+
+#[test]
+fn test_metrics() {
+    let before = heap_metrics();
+    let block = metrics_site();
+    let during = heap_metrics();
+    drop(block);
+    let after = heap_metrics();
+
+    assert!(during.inuse_bytes >= before.inuse_bytes + (1 << 20));
+    assert!(during.allocations > before.allocations);
+    assert!(during.peak_bytes >= during.inuse_bytes);
+    assert!(during.tracked_blocks > 0);
+    assert!(after.frees > during.frees);
+    assert!(after.inuse_bytes < during.inuse_bytes);
+    assert!(after.overhead > Duration::ZERO);
+
+    let text = metrics();
+    for name in [
+        "prof_mem_inuse_bytes",
+        "prof_mem_allocations_total",
+        "prof_mem_frees_total",
+        "prof_mem_peak_inuse_bytes",
+        "prof_mem_tracked_blocks",
+        "prof_mem_overhead_seconds_total",
+    ] {
+        assert!(text.contains(&format!("# TYPE {name} ")), "{text}");
+        let value = text
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{name} ")))
+            .unwrap_or_else(|| panic!("no sample of {name}"));
+        value.parse::<f64>().unwrap();
+    }
+}
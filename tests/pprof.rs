@@ -0,0 +1,49 @@
+use std::{collections::HashMap, hint::black_box};
+
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig, SampleType, dump_to};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn pprof_site() -> Vec<Vec<u8>> {
+    let mut blocks = Vec::new();
+    for _ in 0..16 {
+        blocks.push(vec![1u8; 512]);
+    }
+    black_box(blocks)
+}
+
+#[test]
+fn test_streamed_profile_is_consistent() {
+    let blocks = pprof_site();
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    let profile = HeapProfile::parse(&buf).unwrap();
+
+    // the ids and string indexes of the streamed tables resolve.
+    assert_eq!(
+        profile.sample_types,
+        [SampleType {
+            kind: "space".into(),
+            unit: "bytes".into()
+        }]
+    );
+    assert_eq!(profile.period_type.as_ref().unwrap().kind, "allocations");
+    assert_eq!(profile.period, 1);
+    assert!(profile.time_nanos > 0);
+    assert!(profile.comments.iter().any(|c| c.starts_with("prof-mem")));
+    assert!(profile.samples.iter().all(|s| !s.frames.is_empty()));
+
+    // the labels of the blocks hold their own address.
+    let by_alloc: HashMap<_, _> = profile
+        .samples
+        .iter()
+        .filter_map(|s| Some((s.str_label("alloc")?, s)))
+        .collect();
+    for block in &blocks {
+        let sample = by_alloc[format!("{:p}", block.as_ptr()).as_str()];
+        assert_eq!(sample.values, [512]);
+        assert!(sample.frames[0].function.contains("pprof_site"));
+    }
+}
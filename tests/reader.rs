@@ -0,0 +1,77 @@
+use std::hint::black_box;
+
+use prof_mem::{HeapProfile, LabelValue, ProfAlloc, ProfAllocConfig, dump_to};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().size_labels(true));
+
+#[inline(never)]
+fn reader_site() -> Vec<u8> {
+    black_box(vec![1u8; 3000])
+}
+
+/// a gzip member of `data` with the header fields pprof tools leave out.
+fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut crc = !0u32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    let mut out = vec![0x1f, 0x8b, 8, 1 << 3, 0, 0, 0, 0, 0, 255];
+    out.extend_from_slice(b"mem.pb\0");
+    out.extend(miniz_oxide::deflate::compress_to_vec(data, 6));
+    out.extend((!crc).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+#[test]
+fn test_read_dump() {
+    let block = reader_site();
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+
+    let profile = HeapProfile::parse(&buf).unwrap();
+    assert_eq!(profile.sample_types[0].kind, "space");
+    assert_eq!(profile.sample_types[0].unit, "bytes");
+    assert_eq!(profile.period, 1);
+    assert!(profile.comments.iter().any(|c| c.starts_with("pid: ")));
+
+    let ptr = format!("{:p}", block.as_ptr());
+    let sample = profile
+        .samples
+        .iter()
+        .find(|s| s.str_label("alloc") == Some(ptr.as_str()))
+        .expect("the block should be in the profile");
+    assert_eq!(sample.values, [3000]);
+    assert!(sample.frames[0].function.contains("reader_site"));
+    assert_eq!(
+        sample.label("bytes"),
+        Some(&LabelValue::Num {
+            value: 3000,
+            unit: "bytes".into()
+        })
+    );
+    assert!(sample.num_label("age_ms").is_some());
+    assert!(profile.total(0) >= 3000);
+
+    // the gzip compressed file reads the same.
+    assert_eq!(HeapProfile::parse(&gzip(&buf)).unwrap(), profile);
+}
+
+#[test]
+fn test_read_corrupt() {
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    let mut gz = gzip(&buf);
+    let len = gz.len();
+    gz[len - 5] ^= 1;
+    assert!(HeapProfile::parse(&gz).is_err());
+
+    // a sample at location 1 in a profile without locations.
+    let sample = [0x12, 0x03, 0x0a, 0x01, 0x01];
+    let string_table = [0x32, 0x00];
+    assert!(HeapProfile::parse(&[&sample[..], &string_table].concat()).is_err());
+}
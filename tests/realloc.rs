@@ -0,0 +1,99 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    hint::black_box,
+    ptr,
+    sync::atomic::{AtomicBool, AtomicPtr, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig};
+
+/// Once armed, the block moved by the next reallocation is handed to the next allocation
+/// of 64 bytes, and the reallocation waits for it to be taken: the old address is reused
+/// while the reallocation is still in flight.
+struct Recycling {
+    armed: AtomicBool,
+    slot: AtomicPtr<u8>,
+    taken: AtomicBool,
+}
+
+unsafe impl GlobalAlloc for Recycling {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.size() == 64 {
+            let recycled = self.slot.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !recycled.is_null() {
+                self.taken.store(true, Ordering::Release);
+                return recycled;
+            }
+        }
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        if layout.size() != 64 || !self.armed.swap(false, Ordering::AcqRel) {
+            return unsafe { System.realloc(ptr, layout, new_size) };
+        }
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        let new_ptr = unsafe { System.alloc(new_layout) };
+        unsafe { ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size)) };
+        self.slot.store(ptr, Ordering::Release);
+        let start = Instant::now();
+        while !self.taken.load(Ordering::Acquire) && start.elapsed() < Duration::from_secs(2) {
+            thread::yield_now();
+        }
+        new_ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+}
+
+#[global_allocator]
+static ALLOC: ProfAlloc<Recycling> = ProfAlloc::with_allocator(
+    Recycling {
+        armed: AtomicBool::new(false),
+        slot: AtomicPtr::new(ptr::null_mut()),
+        taken: AtomicBool::new(false),
+    },
+    ProfAllocConfig::new(),
+);
+
+#[test]
+fn test_realloc_address_reused() {
+    let inner = ALLOC.inner();
+    let mut grown = black_box(Vec::<u8>::with_capacity(64));
+    let ready = AtomicBool::new(false);
+    let taken = thread::scope(|s| {
+        let taker = s.spawn(|| {
+            ready.store(true, Ordering::Release);
+            while inner.slot.load(Ordering::Acquire).is_null() {
+                thread::yield_now();
+            }
+            black_box(Box::new([0u8; 64]))
+        });
+        // armed once the thread runs, its start reallocates too.
+        while !ready.load(Ordering::Acquire) {
+            thread::yield_now();
+        }
+        inner.armed.store(true, Ordering::Release);
+        grown.reserve_exact(4096);
+        taker.join().unwrap()
+    });
+    assert!(inner.taken.load(Ordering::Acquire));
+
+    // the block taken meanwhile must not lose its entry to the late untrack of the moved one.
+    let mut buf = Vec::new();
+    prof_mem::dump_to(&mut buf).unwrap();
+    let profile = HeapProfile::parse(&buf).unwrap();
+    let tracked = |ptr: *const u8| {
+        let ptr = format!("{ptr:p}");
+        profile
+            .samples
+            .iter()
+            .any(|sample| sample.str_label("alloc") == Some(ptr.as_str()))
+    };
+    assert!(tracked(taken.as_ptr()));
+    assert!(tracked(grown.as_ptr()));
+}
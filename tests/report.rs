@@ -0,0 +1,53 @@
+use std::hint::black_box;
+
+use prof_mem::{ProfAlloc, ProfAllocConfig, SortBy, print_top, report_top, top_sites};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+// the blocks go to a vector sized by the caller, the sites make no other allocation, and
+// the loops are not unrolled into several call sites.
+#[inline(never)]
+fn heavy_site(blocks: &mut Vec<Vec<u8>>) {
+    for _ in 0..black_box(4) {
+        blocks.push(black_box(vec![1u8; 1 << 20]));
+    }
+}
+
+#[inline(never)]
+fn many_site(blocks: &mut Vec<Vec<u8>>) {
+    for _ in 0..black_box(10_000) {
+        blocks.push(black_box(vec![1u8; 8]));
+    }
+}
+
+#[test]
+fn test_report_top() {
+    let mut heavy = Vec::with_capacity(4);
+    heavy_site(&mut heavy);
+    let mut many = Vec::with_capacity(10_000);
+    many_site(&mut many);
+
+    let by_bytes = top_sites(3, SortBy::InuseBytes);
+    assert!(by_bytes.len() <= 3);
+    let top = &by_bytes[0];
+    assert!(top.frames[0].function.contains("heavy_site"));
+    assert_eq!(
+        (top.inuse_bytes, top.count, top.avg_size()),
+        (4 << 20, 4, 1 << 20)
+    );
+
+    let by_count = top_sites(1, SortBy::Count);
+    assert!(by_count[0].frames[0].function.contains("many_site"));
+    assert_eq!(by_count[0].count, 10_000);
+
+    let report = report_top(5, SortBy::InuseBytes);
+    let first = report.lines().nth(1).unwrap();
+    assert!(first.contains("report::heavy_site"), "{report}");
+    // the release profile has no debug info for the file and line.
+    if cfg!(debug_assertions) {
+        assert!(first.contains("tests/report.rs:"), "{report}");
+    }
+    print_top(5, SortBy::AvgSize).unwrap();
+    drop((heavy, many));
+}
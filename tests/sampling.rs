@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+
+use prof_mem::{HeapProfile, ProfAlloc, ProfAllocConfig, dump_to};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().sample_rate(4));
+
+#[test]
+fn test_sample_rate() {
+    let mut blocks = Vec::with_capacity(400);
+    for _ in 0..400 {
+        blocks.push(Box::new([0u8; 100]));
+    }
+    let mut buf = Vec::new();
+    dump_to(&mut buf).unwrap();
+    let profile = HeapProfile::parse(&buf).unwrap();
+    let blocks: HashSet<_> = blocks.iter().map(|b| format!("{:p}", b.as_ptr())).collect();
+    let sampled: Vec<_> = profile
+        .samples
+        .iter()
+        .filter(|s| s.str_label("alloc").is_some_and(|ptr| blocks.contains(ptr)))
+        .collect();
+    assert_eq!(sampled.len(), 100);
+    assert!(sampled.iter().all(|s| s.values == vec![400]));
+    assert_eq!(profile.period, 4);
+}
+
+#[cfg(feature = "flamegraph")]
+#[inline(never)]
+fn sampled_flame_site() -> Box<[u8; 100]> {
+    std::hint::black_box(Box::new([0u8; 100]))
+}
+
+#[cfg(feature = "flamegraph")]
+#[test]
+fn test_sampled_flamegraph() {
+    use prof_mem::{FlamegraphMetric, write_flamegraph};
+
+    // the sample counter is per thread, a fresh one samples exactly one of four.
+    let blocks = std::thread::spawn(|| (0..400).map(|_| sampled_flame_site()).collect::<Vec<_>>())
+        .join()
+        .unwrap();
+    let mut buf = Vec::new();
+    write_flamegraph(&mut buf, FlamegraphMetric::InuseBytes).unwrap();
+    let svg = String::from_utf8(buf).unwrap();
+    assert!(svg.contains("sampled_flame_site (40000 bytes"), "{svg}");
+    let mut buf = Vec::new();
+    write_flamegraph(&mut buf, FlamegraphMetric::Allocations).unwrap();
+    let svg = String::from_utf8(buf).unwrap();
+    assert!(svg.contains("sampled_flame_site (400 allocations"), "{svg}");
+    drop(blocks);
+}
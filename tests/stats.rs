@@ -0,0 +1,30 @@
+use std::hint::black_box;
+
+use prof_mem::{ProfAlloc, ProfAllocConfig, stats};
+
+// the stats count every call, the tracking being off changes nothing.
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new().enabled(false).sample_rate(1000));
+
+#[test]
+fn test_stats() {
+    let before = stats();
+    let mut block = black_box(Vec::<u8>::with_capacity(1 << 20));
+    let allocated = stats();
+    assert!(allocated.allocated_bytes - before.allocated_bytes >= 1 << 20);
+    assert!(allocated.alloc_calls > before.alloc_calls);
+    assert!(allocated.live_bytes >= 1 << 20);
+    assert!(allocated.live_blocks > 0);
+
+    block.reserve(4 << 20);
+    let grown = stats();
+    assert!(grown.realloc_calls > allocated.realloc_calls);
+    assert!(grown.allocated_bytes - allocated.allocated_bytes >= 4 << 20);
+    assert!(grown.freed_bytes - allocated.freed_bytes >= 1 << 20);
+
+    drop(block);
+    let freed = stats();
+    assert!(freed.dealloc_calls > grown.dealloc_calls);
+    assert!(freed.freed_bytes - grown.freed_bytes >= 4 << 20);
+    assert_eq!(freed.live_bytes, freed.allocated_bytes - freed.freed_bytes);
+}
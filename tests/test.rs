@@ -1,9 +1,9 @@
 use std::thread;
 
-use prof_mem::{ProfAlloc, dump};
+use prof_mem::{ProfAlloc, ProfAllocConfig, dump};
 
 #[global_allocator]
-static ALLOC: ProfAlloc = ProfAlloc(128);
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
 
 #[test]
 fn test_print() {
@@ -0,0 +1,108 @@
+use std::{hint::black_box, time::Duration};
+
+use prof_mem::{ProfAlloc, ProfAllocConfig, Trace, TraceEvent, record_trace};
+
+#[global_allocator]
+static ALLOC: ProfAlloc = ProfAlloc::new(ProfAllocConfig::new());
+
+#[inline(never)]
+fn trace_site(size: usize) -> Vec<u8> {
+    black_box(vec![3u8; size])
+}
+
+#[test]
+fn test_record_trace() {
+    let path = std::env::temp_dir().join(format!("prof-mem-{}.trace", std::process::id()));
+    let before = trace_site(1111);
+
+    let recorder = record_trace(&path).unwrap();
+    // one recording at a time, the rejected one touches no file.
+    let other = path.with_extension("other.trace");
+    assert!(record_trace(&other).is_err());
+    assert!(!other.exists());
+    assert!(record_trace(&path).is_err());
+    let mut grown = trace_site(3333);
+    let freed = trace_site(5555);
+    let freed_ptr = freed.as_ptr() as u64;
+    drop(freed);
+    grown.reserve(100_000);
+    recorder.stop().unwrap();
+
+    let trace = Trace::open(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    // a new recording can start once stopped.
+    record_trace(&other).unwrap().stop().unwrap();
+    assert!(Trace::open(&other).is_ok());
+    std::fs::remove_file(&other).unwrap();
+
+    // the blocks live at the start come first.
+    let started = trace.events.iter().find(|event| {
+        matches!(event, TraceEvent::Alloc { thread: 0, ptr, size: 1111, .. }
+            if *ptr == before.as_ptr() as u64)
+    });
+    assert!(started.is_some());
+
+    let alloc = trace
+        .events
+        .iter()
+        .find_map(|event| match event {
+            TraceEvent::Alloc {
+                time,
+                thread,
+                ptr,
+                size: 3333,
+                stack,
+            } if *thread != 0 => Some((*time, *ptr, *stack)),
+            _ => None,
+        })
+        .unwrap();
+    let frames = &trace.stacks[&alloc.2];
+    assert!(
+        frames
+            .iter()
+            .any(|frame| frame.function.contains("trace_site")),
+        "{frames:?}"
+    );
+    assert!(
+        trace
+            .events
+            .iter()
+            .any(|event| matches!(event, TraceEvent::Free { ptr, .. } if *ptr == freed_ptr))
+    );
+    let realloc = trace
+        .events
+        .iter()
+        .find_map(|event| match event {
+            TraceEvent::Realloc {
+                time,
+                old_ptr,
+                ptr,
+                size,
+                ..
+            } if *old_ptr == alloc.1 => Some((*time, *ptr, *size)),
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(realloc.1, grown.as_ptr() as u64);
+    assert_eq!(realloc.2, grown.capacity() as u64);
+
+    // the heap at the time of each event.
+    let live = trace.live_at(Duration::from_nanos(alloc.0));
+    assert!(
+        live.iter()
+            .any(|block| block.ptr == alloc.1 && block.size == 3333)
+    );
+    let end = trace.events.last().unwrap().time();
+    assert!(end.as_nanos() as u64 >= realloc.0);
+    let live = trace.live_at(end);
+    assert!(
+        live.iter()
+            .any(|block| block.ptr == realloc.1 && block.size == realloc.2)
+    );
+    assert!(
+        live.iter()
+            .all(|block| block.size != 3333 && block.size != 5555)
+    );
+    assert!(live.iter().any(|block| block.size == 1111));
+    drop((before, grown));
+}